@@ -1,5 +1,19 @@
 //use clap::{Arg, Command};
+use chrono::prelude::*;
 use clap::builder::{Arg, ArgAction, Command};
+use clap::parser::ValueSource;
+use clap::{value_parser, ArgMatches};
+
+/// Generate the clap App, but let the shell complete the names of the given saved queries
+pub fn build_with_saved_queries(names: &[String]) -> Command
+{
+    let names: Vec<String> = names.iter().map(|n| format!("@{}", n)).collect();
+    build().mut_subcommand("query", |query| {
+        query.mut_arg("SAVED_QUERY", |arg| {
+            arg.value_parser(clap::builder::PossibleValuesParser::new(names))
+        })
+    })
+}
 
 /// Generate the clap App by using a builer pattern
 pub fn build() -> Command
@@ -17,7 +31,7 @@ pub fn build() -> Command
                 .about("sync the database")
                 .arg(
                     Arg::new("PROJECT")
-                        .display_order(1)
+                        .display_order(2)
                         .conflicts_with_all(["UPDATE", "RENAME", "GENERATE"])
                         .short('p')
                         .long("project")
@@ -79,72 +93,111 @@ pub fn build() -> Command
                 .display_order(2)
                 .short_flag('Q')
                 .about("query the database")
+                // options of saved queries may be overriden on the command line
+                .args_override_self(true)
                 .arg(
-                    Arg::new("TITLE")
-                        .display_order(1)
-                        .short('t')
-                        .long("title")
-                        .num_args(1)
-                        .value_name("REGEX")
-                        .help("keep Zettel with a matching title"),
+                    Arg::new("SAVED_QUERY")
+                        .value_name("@NAME")
+                        .help("run a query saved in the configuration file, with the options below added on top"),
                 )
+                .args(filter_args())
                 .arg(
-                    Arg::new("PROJECT")
-                        .display_order(2)
-                        .short('p')
-                        .long("project")
+                    Arg::new("SEARCH")
+                        .display_order(5)
+                        .short('r')
+                        .long("search")
                         .num_args(1)
-                        .value_name("REGEX")
-                        .help("keep Zettel that are in the matching projects"),
+                        .value_name("TERMS")
+                        .help("rank Zettel by how relevant they are to the search terms"),
                 )
                 .arg(
-                    Arg::new("TAG")
-                        .display_order(3)
-                        .short('g')
-                        .long("tag")
+                    Arg::new("GREP")
+                        .display_order(6)
+                        .conflicts_with_all(["FORMAT", "LINK_SEP", "OUTPUT", "GRAPH", "PATH"])
+                        .long("grep")
+                        .num_args(0)
+                        .requires("TEXT_REGEX")
+                        .action(ArgAction::SetTrue)
+                        .help("helper option to --text; print every matching line as 'path:line:text'"),
+                )
+                .arg(
+                    Arg::new("CONTEXT")
+                        .display_order(7)
+                        .short('C')
+                        .long("context")
                         .num_args(1)
-                        .value_name("REGEX")
-                        .help("keep Zettel that have a matching tag name"),
+                        .value_name("N")
+                        .requires("GREP")
+                        .value_parser(value_parser!(usize))
+                        .help("helper option to --grep; also print N lines before and after every match"),
                 )
                 .arg(
-                    Arg::new("TEXT_REGEX")
-                        .display_order(4)
-                        .short('x')
-                        .long("text")
+                    Arg::new("RELATED")
+                        .display_order(25)
+                        .conflicts_with("SEARCH")
+                        .long("related")
                         .num_args(1)
                         .value_name("REGEX")
-                        .help("keep Zettel that contain some text"),
+                        .help("rank Zettel by how many links they share with the matching Zettel"),
                 )
                 .arg(
-                    Arg::new("LINKS")
-                        .display_order(5)
-                        .short('l')
-                        .long("links")
+                    Arg::new("PATH")
+                        .display_order(26)
+                        .conflicts_with_all(["FORMAT", "LINK_SEP"])
+                        .long("path")
+                        .num_args(2)
+                        .value_names(["FROM", "TO"])
+                        .help("print the shortest chain of links going from one Zettel to another"),
+                )
+                .arg(
+                    Arg::new("PATH_COUNT")
+                        .display_order(27)
+                        .long("paths")
                         .num_args(1)
-                        .value_name("REGEX")
-                        .help("keep Zettel that have links to the matching Zettel"),
+                        .value_name("COUNT")
+                        .requires("PATH")
+                        .default_value("1")
+                        .value_parser(value_parser!(usize))
+                        .help("helper option to --path; find up to COUNT of the shortest paths"),
                 )
                 .arg(
-                    Arg::new("BACKLINKS")
-                        .display_order(6)
-                        .short('b')
-                        .long("backlinks")
+                    Arg::new("UNDIRECTED")
+                        .display_order(28)
+                        .long("undirected")
+                        .num_args(0)
+                        .requires("PATH")
+                        .action(ArgAction::SetTrue)
+                        .help("helper option to --path; allow walking through backlinks as well"),
+                )
+                .arg(
+                    Arg::new("SORT")
+                        .display_order(29)
+                        .long("sort")
                         .num_args(1)
-                        .value_name("REGEX")
-                        .help("keep Zettel that have links from the matching Zettel"),
+                        .value_name("KEY")
+                        .value_parser(["title", "project", "links", "backlinks", "tags", "mtime", "score"])
+                        .help("sort the results by title, project, number of links, backlinks or tags, modification time, or search score"),
                 )
                 .arg(
-                    Arg::new("LONERS")
-                        .display_order(7)
-                        .short('o')
-                        .long("loners")
+                    Arg::new("REVERSE")
+                        .display_order(30)
+                        .long("reverse")
                         .num_args(0)
                         .action(ArgAction::SetTrue)
-                        .help("keep Zettel that don't have any links to and fro"),
+                        .help("reverse the order of the results"),
+                )
+                .arg(
+                    Arg::new("LIMIT")
+                        .display_order(31)
+                        .long("limit")
+                        .num_args(1)
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .help("keep only the first N results, after sorting"),
                 )
                 .arg(
                     Arg::new("FORMAT")
-                        .display_order(8)
+                        .display_order(32)
                         .short('f')
                         .long("format")
                         .num_args(1)
@@ -152,7 +205,7 @@ pub fn build() -> Command
                 )
                 .arg(
                     Arg::new("LINK_SEP")
-                        .display_order(9)
+                        .display_order(33)
                         .short('s')
                         .long("link_sep")
                         .num_args(1)
@@ -160,23 +213,85 @@ pub fn build() -> Command
                         .requires("FORMAT")
                         .help("specify separator for links and backlinks in formatted output"),
                 )
+                .arg(
+                    Arg::new("HEADER")
+                        .display_order(34)
+                        .conflicts_with_all(["GRAPH", "GREP", "PATH"])
+                        .long("header")
+                        .num_args(1)
+                        .value_name("TEXT")
+                        .help("print some text before the formatted results"),
+                )
+                .arg(
+                    Arg::new("FOOTER")
+                        .display_order(35)
+                        .conflicts_with_all(["GRAPH", "GREP", "PATH"])
+                        .long("footer")
+                        .num_args(1)
+                        .value_name("TEXT")
+                        .help("print some text after the formatted results"),
+                )
+                .arg(
+                    Arg::new("OUTPUT")
+                        .display_order(36)
+                        .conflicts_with_all(["FORMAT", "LINK_SEP", "HEADER", "FOOTER", "GRAPH", "PATH"])
+                        .long("output")
+                        .num_args(1)
+                        .value_parser(["json", "ndjson", "csv", "tsv"])
+                        .help("print all the data of the results as 'json', 'ndjson', 'csv' or 'tsv'"),
+                )
                 .arg(
                     Arg::new("GRAPH")
                         .conflicts_with_all(["FORMAT", "LINK_SEP"])
-                        .display_order(10)
+                        .display_order(37)
                         .long("graph")
                         .num_args(1)
                         //.action(ArgAction::SetTrue)
-                        .help("turn the query results into a graph: 'dot', 'json' or 'vizk'"),
+                        .help(
+                            "turn the query results into a graph: 'dot', 'json', 'vizk', 'graphml', \
+                             'gexf', 'csv-nodes', 'csv-edges', 'mermaid' or 'svg'",
+                        ),
                 )
                 .arg(
-                    Arg::new("EXACT_MATCH")
-                        .display_order(11)
-                        .short('e')
-                        .long("exact")
-                        .num_args(0)
+                    Arg::new("SUBGRAPH")
+                        .requires("GRAPH")
+                        .display_order(38)
+                        .long("subgraph")
+                        .num_args(1)
+                        .value_name("MODE")
+                        .value_parser(["induced", "linked", "expanded"])
+                        .help(
+                            "helper option to --graph; keep only the links between the results \
+                             ('induced'), also show the notes they link to ('linked', the default), \
+                             or also show the notes that are a few hops away ('expanded')",
+                        ),
+                )
+                .arg(
+                    Arg::new("HOPS")
+                        .requires("SUBGRAPH")
+                        .display_order(38)
+                        .long("hops")
+                        .num_args(1)
+                        .value_name("HOPS")
+                        .default_value("1")
+                        .value_parser(value_parser!(usize))
+                        .help("helper option to --subgraph expanded; specify the maximum number of hops"),
+                )
+                .arg(
+                    Arg::new("WITH_TAGS")
+                        .requires("GRAPH")
+                        .display_order(38)
+                        .long("with-tags")
+                        .action(ArgAction::SetTrue)
+                        .help("add a node for every tag, which the notes with that tag link to"),
+                )
+                .arg(
+                    Arg::new("BY_PROJECT")
+                        .requires("GRAPH")
+                        .display_order(38)
+                        .long("by-project")
                         .action(ArgAction::SetTrue)
-                        .help("match everything exactly, disabling regex"),
+                        .help("group the notes of every project into a subgraph (for 'dot' and 'mermaid')"),
                 ),
         )
         .subcommand(
@@ -190,9 +305,529 @@ pub fn build() -> Command
                 ),
         )
         .subcommand(
-            Command::new("compl")
+            Command::new("pick")
+                .display_order(4)
+                .about("interactively pick a note among those matching the filters, and print its path")
+                .args(filter_args())
+                .arg(
+                    Arg::new("FORMAT")
+                        .display_order(39)
+                        .short('f')
+                        .long("format")
+                        .num_args(1)
+                        .help("print the picked note formatted, instead of printing its path"),
+                )
+                .arg(
+                    Arg::new("LINK_SEP")
+                        .display_order(40)
+                        .short('s')
+                        .long("link_sep")
+                        .num_args(1)
+                        .value_name("SEPARATOR")
+                        .requires("FORMAT")
+                        .help("specify separator for links and backlinks in formatted output"),
+                ),
+        )
+        .subcommand(
+            Command::new("suggest")
                 .display_order(5)
+                .about("suggest links between notes with similar contents")
+                .arg(
+                    Arg::new("TITLE")
+                        .help("suggest links for this note only, instead of for the whole Zettelkasten"),
+                )
+                .arg(
+                    Arg::new("LIMIT")
+                        .display_order(1)
+                        .short('n')
+                        .long("limit")
+                        .num_args(1)
+                        .value_name("N")
+                        .default_value("10")
+                        .value_parser(value_parser!(usize))
+                        .help("print only the N most similar notes or pairs of notes"),
+                )
+                .arg(
+                    Arg::new("FORMAT")
+                        .display_order(2)
+                        .short('f')
+                        .long("format")
+                        .num_args(1)
+                        .requires("TITLE")
+                        .help("print the suggested notes formatted"),
+                )
+                .arg(
+                    Arg::new("LINK_SEP")
+                        .display_order(3)
+                        .short('s')
+                        .long("link_sep")
+                        .num_args(1)
+                        .value_name("SEPARATOR")
+                        .requires("FORMAT")
+                        .help("specify separator for links and backlinks in formatted output"),
+                ),
+        )
+        .subcommand(
+            Command::new("duplicates")
+                .display_order(6)
+                .about("find pairs of notes whose contents are nearly the same")
+                .arg(
+                    Arg::new("THRESHOLD")
+                        .display_order(1)
+                        .short('t')
+                        .long("threshold")
+                        .num_args(1)
+                        .value_name("SIMILARITY")
+                        .default_value("0.8")
+                        .value_parser(value_parser!(f64))
+                        .help("print only the pairs that are at least this similar, between 0 and 1"),
+                ),
+        )
+        .subcommand(
+            Command::new("centrality")
+                .display_order(7)
+                .about("rank the notes among those matching the filters by how central they are")
+                .args(filter_args())
+                .arg(
+                    Arg::new("SORT")
+                        .display_order(38)
+                        .long("sort")
+                        .num_args(1)
+                        .value_name("METRIC")
+                        .default_value("pagerank")
+                        .value_parser(["pagerank", "betweenness", "in", "out"])
+                        .help("rank notes by PageRank, betweenness centrality, or number of backlinks (in) or links (out)"),
+                )
+                .arg(
+                    Arg::new("LIMIT")
+                        .display_order(39)
+                        .short('n')
+                        .long("limit")
+                        .num_args(1)
+                        .value_name("N")
+                        .value_parser(value_parser!(usize))
+                        .help("print only the N most central notes"),
+                )
+                .arg(
+                    Arg::new("OUTPUT")
+                        .display_order(40)
+                        .long("output")
+                        .num_args(1)
+                        .default_value("table")
+                        .value_parser(["table", "json", "vizk"])
+                        .help("print a 'table', 'json', or a 'vizk' graph whose nodes are sized by the metric"),
+                ),
+        )
+        .subcommand(
+            Command::new("clusters")
+                .display_order(8)
+                .about("group the notes among those matching the filters into clusters of closely linked notes")
+                .args(filter_args())
+                .arg(
+                    Arg::new("METHOD")
+                        .display_order(38)
+                        .long("method")
+                        .num_args(1)
+                        .default_value("louvain")
+                        .value_parser(["louvain", "components"])
+                        .help("find densely linked communities ('louvain'), or groups of connected notes ('components')"),
+                )
+                .arg(
+                    Arg::new("MIN_SIZE")
+                        .display_order(39)
+                        .long("min-size")
+                        .num_args(1)
+                        .value_name("N")
+                        .default_value("2")
+                        .value_parser(value_parser!(usize))
+                        .help("print only the clusters that have at least N notes"),
+                )
+                .arg(
+                    Arg::new("OUTPUT")
+                        .display_order(40)
+                        .long("output")
+                        .num_args(1)
+                        .default_value("list")
+                        .value_parser(["list", "json"])
+                        .help("print the clusters as a 'list' or as 'json'"),
+                ),
+        )
+        .subcommand(
+            Command::new("cycles")
+                .display_order(9)
+                .about("find the chains of links among the notes matching the filters that lead back to where they started")
+                .args(filter_args())
+                .arg(
+                    Arg::new("MAX_LENGTH")
+                        .display_order(38)
+                        .long("max-length")
+                        .num_args(1)
+                        .value_name("N")
+                        .default_value("5")
+                        .value_parser(value_parser!(usize))
+                        .help("print only the cycles that are made up of at most N notes"),
+                )
+                .arg(
+                    Arg::new("COMPONENTS")
+                        .display_order(39)
+                        .conflicts_with("MAX_LENGTH")
+                        .long("components")
+                        .num_args(0)
+                        .action(ArgAction::SetTrue)
+                        .help("print the strongly connected components, i.e. the groups of notes that can all reach each other"),
+                )
+                .arg(
+                    Arg::new("OUTPUT")
+                        .display_order(40)
+                        .long("output")
+                        .num_args(1)
+                        .default_value("list")
+                        .value_parser(["list", "dot"])
+                        .help("print the cycles as a 'list', or as 'dot' subgraphs"),
+                ),
+        )
+        .subcommand(
+            Command::new("compl")
+                .display_order(10)
                 .arg(Arg::new("SHELL").required(true))
                 .about("generate completion file for a given shell"),
         )
+        .mut_subcommand("query", negatable_flags)
+}
+
+/// Give every flag of the given command a hidden `--no-FLAG` counterpart, so that the flags set by
+/// a saved query can be turned off on the command line; whichever of the two comes last wins
+fn negatable_flags(cmd: Command) -> Command
+{
+    let flags: Vec<(String, String)> = cmd
+        .get_arguments()
+        .filter(|a| matches!(a.get_action(), ArgAction::SetTrue))
+        .filter_map(|a| Some((a.get_id().to_string(), a.get_long()?.to_string())))
+        .collect();
+    flags.into_iter().fold(cmd, |cmd, (id, long)| {
+        let negation = format!("NO_{}", id);
+        cmd.mut_arg(&id, |a| a.overrides_with(&negation)).arg(
+            Arg::new(&negation)
+                .long(format!("no-{}", long))
+                .num_args(0)
+                .action(ArgAction::SetTrue)
+                .overrides_with(&id)
+                .hide(true),
+        )
+    })
+}
+
+/// Return the arguments that were given to a subcommand on the command line, so that they can be
+/// parsed again along with others
+///
+/// Positional arguments are left out, as are the options that were set to their default value.
+pub fn given_args(cmd: &Command, matches: &ArgMatches) -> Vec<String>
+{
+    let mut args = vec![];
+    for arg in cmd.get_arguments() {
+        let (id, long) = match arg.get_long() {
+            Some(long) => (arg.get_id().as_str(), long),
+            None => continue,
+        };
+        if matches.value_source(id) != Some(ValueSource::CommandLine) {
+            continue;
+        }
+        if !arg.get_action().takes_values() {
+            args.push(format!("--{}", long));
+            continue;
+        }
+        let values: Vec<String> = matches
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|v| v.to_string_lossy().into_owned())
+            .collect();
+        // a single value may well start with a dash, which mustn't be mistaken for an option
+        if let [value] = values.as_slice() {
+            args.push(format!("--{}={}", long, value));
+        } else {
+            args.push(format!("--{}", long));
+            args.extend(values);
+        }
+    }
+    args
+}
+
+/// The options that filter Zettel, which are shared by `query`, `pick`, `centrality`,
+/// `clusters` and `cycles`
+fn filter_args() -> Vec<Arg>
+{
+    vec![
+        Arg::new("TITLE")
+            .display_order(1)
+            .short('t')
+            .long("title")
+            .num_args(1)
+            .value_name("REGEX")
+            .help("keep Zettel with a matching title"),
+        Arg::new("PROJECT")
+            .display_order(2)
+            .short('p')
+            .long("project")
+            .num_args(1)
+            .value_name("REGEX")
+            .help("keep Zettel that are in the matching projects"),
+        Arg::new("TAG")
+            .display_order(3)
+            .short('g')
+            .long("tag")
+            .num_args(1)
+            .value_name("REGEX")
+            .help("keep Zettel that have a matching tag name"),
+        Arg::new("TEXT_REGEX")
+            .display_order(4)
+            .short('x')
+            .long("text")
+            .num_args(1)
+            .value_name("REGEX")
+            .help("keep Zettel that contain some text"),
+        Arg::new("LINKS")
+            .display_order(8)
+            .short('l')
+            .long("links")
+            .num_args(1)
+            .value_name("REGEX")
+            .help("keep Zettel that have links to the matching Zettel"),
+        Arg::new("BACKLINKS")
+            .display_order(9)
+            .short('b')
+            .long("backlinks")
+            .num_args(1)
+            .value_name("REGEX")
+            .help("keep Zettel that have links from the matching Zettel"),
+        Arg::new("MODIFIED_SINCE")
+            .display_order(10)
+            .long("modified-since")
+            .num_args(1)
+            .value_name("DATE")
+            .value_parser(parse_date)
+            .help("keep Zettel that were modified at or after DATE"),
+        Arg::new("MODIFIED_BEFORE")
+            .display_order(11)
+            .long("modified-before")
+            .num_args(1)
+            .value_name("DATE")
+            .value_parser(parse_date)
+            .help("keep Zettel that were last modified before DATE"),
+        Arg::new("CREATED_SINCE")
+            .display_order(12)
+            .long("created-since")
+            .num_args(1)
+            .value_name("DATE")
+            .value_parser(parse_date)
+            .help("keep Zettel that were created at or after DATE"),
+        Arg::new("CREATED_BEFORE")
+            .display_order(13)
+            .long("created-before")
+            .num_args(1)
+            .value_name("DATE")
+            .value_parser(parse_date)
+            .help("keep Zettel that were created before DATE"),
+        Arg::new("LONERS")
+            .display_order(14)
+            .short('o')
+            .long("loners")
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .help("keep Zettel that don't have any links to and fro"),
+        Arg::new("MIN_LINKS")
+            .display_order(15)
+            .long("min-links")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(value_parser!(usize))
+            .help("keep Zettel that have at least N links to other notes"),
+        Arg::new("MAX_LINKS")
+            .display_order(16)
+            .long("max-links")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(value_parser!(usize))
+            .help("keep Zettel that have at most N links to other notes"),
+        Arg::new("MIN_BACKLINKS")
+            .display_order(17)
+            .long("min-backlinks")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(value_parser!(usize))
+            .help("keep Zettel that have at least N links from other notes"),
+        Arg::new("MAX_BACKLINKS")
+            .display_order(18)
+            .long("max-backlinks")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(value_parser!(usize))
+            .help("keep Zettel that have at most N links from other notes"),
+        Arg::new("DEAD_ENDS")
+            .display_order(19)
+            .long("dead-ends")
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .help("keep Zettel that don't have any links to other notes"),
+        Arg::new("SOURCES")
+            .display_order(20)
+            .long("sources")
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .help("keep Zettel that don't have any links from other notes"),
+        Arg::new("GHOST_LINKS")
+            .display_order(21)
+            .long("ghost-links")
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .help("keep Zettel whose links all point to notes that don't exist yet"),
+        Arg::new("NEAR")
+            .display_order(22)
+            .long("near")
+            .num_args(1)
+            .value_name("REGEX")
+            .help("keep Zettel that are within a few hops of the matching Zettel"),
+        Arg::new("DEPTH")
+            .display_order(23)
+            .long("depth")
+            .num_args(1)
+            .value_name("HOPS")
+            .requires("NEAR")
+            .default_value("1")
+            .value_parser(value_parser!(usize))
+            .help("helper option to --near; specify the maximum number of hops"),
+        Arg::new("DIRECTION")
+            .display_order(24)
+            .long("direction")
+            .num_args(1)
+            .requires("NEAR")
+            .default_value("both")
+            .value_parser(["out", "in", "both"])
+            .help("helper option to --near; follow forward links, backlinks or both"),
+        Arg::new("EXACT_MATCH")
+            .display_order(38)
+            .short('e')
+            .long("exact")
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .help("match everything exactly, disabling regex"),
+    ]
+}
+
+/// Parse a date given on the command line into a UNIX timestamp
+///
+/// A date is either relative to the current time, e.g. `3h`, `7d`, `2w`, `6m`, `1y` for that many
+/// hours, days, weeks, months or years ago, or absolute, e.g. `2023-10-28`, `2023-10-28 14:30` or
+/// an RFC 3339 timestamp. Absolute dates without a timezone are in local time.
+fn parse_date(date: &str) -> Result<i64, String>
+{
+    let date = date.trim();
+    if let Some(unit) = date.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        if let Ok(amount) = date[..date.len() - 1].parse::<i64>() {
+            const HOUR: i64 = 60 * 60;
+            const DAY: i64 = 24 * HOUR;
+            let unit_secs = match unit {
+                'h' => HOUR,
+                'd' => DAY,
+                'w' => 7 * DAY,
+                'm' => 30 * DAY,
+                'y' => 365 * DAY,
+                _ => {
+                    return Err(format!(
+                        "unknown unit '{}'; expected one of h, d, w, m, y",
+                        unit
+                    ))
+                }
+            };
+            return amount
+                .checked_mul(unit_secs)
+                .and_then(|ago| Local::now().timestamp().checked_sub(ago))
+                .ok_or_else(|| format!("'{}' is too far away", date));
+        }
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Ok(datetime.timestamp());
+    }
+    let naive = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|f| NaiveDateTime::parse_from_str(date, f).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    });
+    naive
+        .and_then(|n| Local.from_local_datetime(&n).earliest())
+        .map(|d| d.timestamp())
+        .ok_or_else(|| {
+            "expected a date such as '2023-10-28' or '2023-10-28 14:30', or a relative one such as '7d' or '2w'".to_string()
+        })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn parse_absolute_dates()
+    {
+        assert_eq!(parse_date("2023-10-28T14:30:00+02:00"), Ok(1698496200));
+        let day = parse_date("2023-10-28").unwrap();
+        assert_eq!(
+            parse_date("2023-10-28 14:30"),
+            Ok(day + 14 * 3600 + 30 * 60)
+        );
+        assert_eq!(
+            parse_date("2023-10-28T14:30:15"),
+            Ok(day + 14 * 3600 + 30 * 60 + 15)
+        );
+        assert_eq!(parse_date("  2023-10-28 "), Ok(day));
+    }
+
+    #[test]
+    fn parse_relative_dates()
+    {
+        let hour = 3600;
+        let day = 24 * hour;
+        for (date, ago) in [
+            ("3h", 3 * hour),
+            ("7d", 7 * day),
+            ("2w", 14 * day),
+            ("6m", 180 * day),
+            ("1y", 365 * day),
+            ("-1d", -day),
+        ] {
+            let now = Local::now().timestamp();
+            let parsed = parse_date(date).unwrap();
+            // a second may go by between the two
+            assert!((now - ago..=now - ago + 1).contains(&parsed), "{}", date);
+        }
+    }
+
+    #[test]
+    fn reject_bad_dates()
+    {
+        for date in ["", "yesterday", "7x", "d", "2023-13-01", "28/10/2023"] {
+            assert!(parse_date(date).is_err(), "{}", date);
+        }
+    }
+
+    #[test]
+    fn reject_dates_too_far_away()
+    {
+        for date in [
+            "9223372036854775807h",
+            "300000000000000000y",
+            "-9223372036854775808w",
+        ] {
+            assert!(parse_date(date).is_err(), "{}", date);
+        }
+    }
 }
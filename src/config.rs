@@ -1,5 +1,7 @@
 use crate::io::{dir_exists, dirname, file_exists, file_to_string, mkdir, write_to_file};
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
 use std::env;
 
 /// The location of the database file. Unchangeable: the user doesn't need to know the location of
@@ -11,6 +13,53 @@ pub struct ConfigOptions
 {
     pub zettelkasten: String,
     pub template: String,
+    /// Named `query` options, indexed by the long name of each option
+    #[serde(default)]
+    pub queries: BTreeMap<String, BTreeMap<String, Value>>,
+    /// How graphs are drawn in the DOT format
+    #[serde(default)]
+    pub graph_style: GraphStyle,
+}
+
+/// What the nodes of a DOT graph are coloured by
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorBy
+{
+    #[default]
+    None,
+    /// The first tag of the note
+    Tag,
+    Project,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GraphStyle
+{
+    /// Whether the notes of every project are grouped into a cluster
+    pub clusters: bool,
+    pub color_by: ColorBy,
+    /// The colours that are given out, in order, to every tag or project
+    pub palette: Vec<String>,
+}
+
+impl Default for GraphStyle
+{
+    fn default() -> GraphStyle
+    {
+        GraphStyle {
+            clusters: true,
+            color_by: ColorBy::None,
+            palette: [
+                "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462", "#b3de69",
+                "#fccde5",
+            ]
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+        }
+    }
 }
 
 impl ::std::default::Default for ConfigOptions
@@ -20,6 +69,8 @@ impl ::std::default::Default for ConfigOptions
         ConfigOptions {
             zettelkasten: format!("{}/zettelkasten", env::var("HOME").unwrap()),
             template: String::from(""),
+            queries: BTreeMap::new(),
+            graph_style: GraphStyle::default(),
         }
     }
 }
@@ -30,6 +81,46 @@ impl ConfigOptions
     {
         format!("{}/{}", &self.zettelkasten, DATABASE_FILE)
     }
+
+    /// Turn the saved query with the given name into command line arguments, or return nothing if
+    /// there's no such query
+    ///
+    /// Strings and numbers are passed as the value of the option, lists are passed as multiple
+    /// values, and booleans specify whether a flag is set or not.
+    ///
+    /// A single value is joined to its option with `=`, so that a value that starts with a dash,
+    /// such as a relative date, isn't mistaken for an option.
+    pub fn saved_query_args(&self, name: &str) -> Option<Vec<String>>
+    {
+        let options = self.queries.get(name)?;
+        let mut args = vec![];
+        for (option, value) in options {
+            let values: Vec<String> = match value {
+                Value::Bool(false) | Value::Null => continue,
+                Value::Bool(true) => vec![],
+                Value::Sequence(seq) => seq.iter().map(value_to_string).collect(),
+                v => vec![value_to_string(v)],
+            };
+            if let [value] = values.as_slice() {
+                args.push(format!("--{}={}", option, value));
+            } else {
+                args.push(format!("--{}", option));
+                args.extend(values);
+            }
+        }
+        Some(args)
+    }
+}
+
+/// Return the string representation of a scalar YAML value
+fn value_to_string(value: &Value) -> String
+{
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => String::new(),
+    }
 }
 
 /// Given a path, expand environment variables and tilde at beginning if it exists
@@ -70,6 +161,8 @@ impl ConfigOptions
         let cfg = ConfigOptions {
             zettelkasten: expand_path(&tmp.zettelkasten),
             template: expand_path(&tmp.template),
+            queries: tmp.queries,
+            graph_style: tmp.graph_style,
         };
 
         // Create the Zettelkasten directory it doesn't exist already
@@ -80,6 +173,19 @@ impl ConfigOptions
         cfg
     }
 
+    /// Return the names of the saved queries in the configuration file, without creating the file
+    /// if it doesn't exist
+    pub fn saved_query_names() -> Vec<String>
+    {
+        let config_file = Self::cfg_file();
+        if !file_exists(&config_file) {
+            return vec![];
+        }
+        serde_yaml::from_str::<ConfigOptions>(&file_to_string(&config_file))
+            .map(|cfg| cfg.queries.into_keys().collect())
+            .unwrap_or_default()
+    }
+
     // The configuration is determined by looking at the environment variables in this order:
     // 1. If `$SETTLE_CONFIG` is set: `$SETTLE_CONFIG`
     // 2. If `$XDG_CONFIG_HOME` is set: `$XDG_CONFIG_HOME/settle/settle.yaml`
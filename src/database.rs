@@ -1,8 +1,10 @@
 use crate::{config::ConfigOptions, zettel::Zettel};
 use rayon::prelude::*;
 use rusqlite::{
-    named_params, Connection, DatabaseName, Error, Result, Row, Transaction, TransactionBehavior,
+    named_params, params, Connection, DatabaseName, Error, Result, Row, Transaction,
+    TransactionBehavior,
 };
+use std::collections::HashMap;
 use std::sync::{mpsc, Arc, Mutex, MutexGuard};
 use std::thread;
 
@@ -15,6 +17,13 @@ impl Zettel
         let title: String = row.get(0)?;
         let project: String = row.get(1)?;
         let mut z = Zettel::new(&title, &project);
+        z.created = row.get(2)?;
+        z.modified = row.get(3)?;
+        let fingerprint: Vec<u8> = row.get::<_, Option<Vec<u8>>>(4)?.unwrap_or_default();
+        z.fingerprint = fingerprint
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
 
         let mut stmt = conn_lock.prepare("SELECT link_id FROM links WHERE zettel_id = ?1")?;
         let mut links = stmt.query([&z.title])?;
@@ -69,10 +78,37 @@ impl Database
             "CREATE TABLE IF NOT EXISTS zettelkasten (
                                                 title       TEXT NOT NULL,
                                                 project     TEXT,
+                                                created     INTEGER NOT NULL DEFAULT 0,
+                                                modified    INTEGER NOT NULL DEFAULT 0,
+                                                fingerprint BLOB,
                                                 UNIQUE(title)
                                             )",
             [],
         )?;
+        // Databases made by older versions don't keep track of times
+        let has_times: bool = conn_lock.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('zettelkasten') WHERE name = 'modified'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_times {
+            conn_lock.execute(
+                "ALTER TABLE zettelkasten ADD COLUMN created INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+            conn_lock.execute(
+                "ALTER TABLE zettelkasten ADD COLUMN modified INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        let has_fingerprint: bool = conn_lock.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('zettelkasten') WHERE name = 'fingerprint'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_fingerprint {
+            conn_lock.execute("ALTER TABLE zettelkasten ADD COLUMN fingerprint BLOB", [])?;
+        }
         conn_lock.execute("CREATE TABLE IF NOT EXISTS links (
                                                 zettel_id   TEXT,
                                                 link_id     TEXT,
@@ -113,8 +149,18 @@ impl Database
     pub fn save_tsx(tsx: &Transaction, zettel: &Zettel) -> Result<(), Error>
     {
         tsx.execute(
-            "INSERT INTO zettelkasten (title, project) values (?1, ?2)",
-            [&zettel.title, &zettel.project],
+            "INSERT INTO zettelkasten (title, project, created, modified, fingerprint) values (?1, ?2, ?3, ?4, ?5)",
+            params![
+                &zettel.title,
+                &zettel.project,
+                zettel.created,
+                zettel.modified,
+                zettel
+                    .fingerprint
+                    .iter()
+                    .flat_map(|h| h.to_le_bytes())
+                    .collect::<Vec<u8>>()
+            ],
         )?;
         for link in &zettel.links {
             tsx.execute(
@@ -147,7 +193,8 @@ impl Database
     pub fn all(&self) -> Result<Vec<Zettel>, Error>
     {
         let conn_lock = self.conn.lock().unwrap();
-        let mut stmt = conn_lock.prepare("SELECT * FROM zettelkasten")?;
+        let mut stmt = conn_lock
+            .prepare("SELECT title, project, created, modified, fingerprint FROM zettelkasten")?;
         let mut rows = stmt.query([])?;
 
         let mut results: Vec<Zettel> = Vec::new();
@@ -167,7 +214,9 @@ impl Database
     pub fn find_by_title(&self, pattern: &str) -> Result<Vec<Zettel>, Error>
     {
         let conn_lock = self.conn.lock().unwrap();
-        let mut stmt = conn_lock.prepare("SELECT * FROM zettelkasten WHERE title LIKE :pattern")?;
+        let mut stmt = conn_lock.prepare(
+            "SELECT title, project, created, modified, fingerprint FROM zettelkasten WHERE title LIKE :pattern",
+        )?;
         let mut rows = stmt.query(named_params! {":pattern": pattern})?;
 
         let mut results: Vec<Zettel> = Vec::new();
@@ -195,6 +244,22 @@ impl Database
         Ok(results)
     }
 
+    /// Return the titles of all the Zettel in the database
+    ///
+    /// Return an Error if the database was unreachable
+    pub fn list_titles(&self) -> Result<Vec<String>, Error>
+    {
+        let conn_lock = self.conn.lock().unwrap();
+        let mut stmt = conn_lock.prepare("SELECT title FROM zettelkasten")?;
+        let mut rows = stmt.query([])?;
+
+        let mut results: Vec<String> = Vec::new();
+        while let Some(row) = rows.next()? {
+            results.push(row.get(0)?);
+        }
+        Ok(results)
+    }
+
     /// Return a list of all unique project names found in the database
     ///
     /// Return an Error if the database was unreachable
@@ -237,7 +302,11 @@ impl Database
 
     /// Look for Markdown files in the Zettelkasten directory and populate the database with their
     /// metadata
-    pub fn generate(&self, cfg: &ConfigOptions) -> Result<(), Error>
+    ///
+    /// The notes whose titles are in `created` keep the creation time given there, rather than
+    /// the one of their file.
+    pub fn generate(&self, cfg: &ConfigOptions, created: &HashMap<String, i64>)
+        -> Result<(), Error>
     {
         let mut directories = crate::io::list_subdirectories(&cfg.zettelkasten);
 
@@ -285,7 +354,10 @@ impl Database
                                                                     })
                                                                     .collect();
                                     paths.par_iter().for_each(|path| {
-                                                    let zettel = Zettel::from_file(cfg, path);
+                                                    let mut zettel = Zettel::from_file(cfg, path);
+                                                    if let Some(c) = created.get(&zettel.title) {
+                                                        zettel.created = *c;
+                                                    }
                                                     tx.send(zettel).unwrap();
                                     });
         });
@@ -297,14 +369,33 @@ impl Database
 
     /// Update the metadata for a given Zettel. The specified path *must* exist
     /// Not practical for a bunch of Zettel. Use `generate` instead.
+    ///
+    /// The creation time that's already in the database is kept: editors that save a file by
+    /// replacing it give it a new one every time.
     pub fn update(&self, cfg: &ConfigOptions, zettel: &Zettel) -> Result<(), Error>
     {
+        let created = self.creation_times(Some(&zettel.title))?;
         self.delete(zettel)?;
-        let z = &Zettel::from_file(cfg, &zettel.filename(cfg));
-        self.save(z)?;
+        let mut z = Zettel::from_file(cfg, &zettel.filename(cfg));
+        if let Some(c) = created.get(&z.title) {
+            z.created = *c;
+        }
+        self.save(&z)?;
         Ok(())
     }
 
+    /// Return the recorded creation time of the Zettel with the given title, or of every Zettel
+    /// if there's no title, indexed by title; unknown creation times are left out
+    pub fn creation_times(&self, title: Option<&str>) -> Result<HashMap<String, i64>, Error>
+    {
+        let conn_lock = self.conn.lock().unwrap();
+        let mut stmt = conn_lock.prepare(
+            "SELECT title, created FROM zettelkasten WHERE created != 0 AND (?1 IS NULL OR title = ?1)",
+        )?;
+        let rows = stmt.query_map([title], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
     /// Change the project of the given Zettel within the database
     pub fn change_project(&self, zettel: &Zettel, new_project: &str) -> Result<(), Error>
     {
@@ -325,3 +416,25 @@ impl Database
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn only_known_creation_times_are_returned()
+    {
+        let db = Database::new_in_memory("creation_times_test").unwrap();
+        db.init().unwrap();
+        let mut a = Zettel::new("A", "");
+        a.created = 10;
+        db.save(&a).unwrap();
+        db.save(&Zettel::new("B", "")).unwrap();
+
+        let all = db.creation_times(None).unwrap();
+        assert_eq!(all, HashMap::from([("A".to_string(), 10)]));
+        assert_eq!(db.creation_times(Some("A")).unwrap(), all);
+        assert!(db.creation_times(Some("B")).unwrap().is_empty());
+    }
+}
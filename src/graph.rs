@@ -1,80 +1,1628 @@
+use crate::config::{ColorBy, GraphStyle};
+use crate::printer::escape_csv;
 use crate::Zettel;
-use petgraph::dot::{Config, Dot};
+use petgraph::algo::{astar, tarjan_scc};
 use petgraph::graph::NodeIndex;
-use petgraph::Graph;
+use petgraph::visit::{EdgeFiltered, EdgeRef};
+use petgraph::{Graph, Incoming, Outgoing};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The probability, in PageRank, that a random reader follows a link instead of jumping to a random
+/// note
+const DAMPING: f64 = 0.85;
+/// PageRank stops once the scores change by less than this much in total...
+const PAGERANK_TOLERANCE: f64 = 1e-10;
+/// ...or after this many iterations, whichever comes first
+const PAGERANK_ITERATIONS: usize = 100;
+
+/// The length that links tend towards in the SVG layout, in pixels
+const LAYOUT_DISTANCE: f64 = 80.0;
+/// The number of steps taken by the SVG layout, which slows down linearly until it stops
+const LAYOUT_ITERATIONS: usize = 300;
+/// How strongly every node is pulled towards the centre of the SVG layout, which keeps the notes
+/// that aren't linked to anything from drifting away
+const LAYOUT_GRAVITY: f64 = 0.5;
+/// The radius of the nodes in the SVG graph, in pixels
+const NODE_RADIUS: f64 = 6.0;
+
+/// The links that are followed when walking through the Zettelkasten
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction
+{
+    /// Forward links only
+    Out,
+    /// Backlinks only
+    In,
+    /// Both forward links and backlinks
+    Both,
+}
+
+/// How central a note is within the graph of the Zettelkasten
+#[derive(Serialize)]
+pub struct Centrality
+{
+    pub title: String,
+    /// Whether the note doesn't exist, but is linked to
+    pub ghost: bool,
+    pub pagerank: f64,
+    /// Number of backlinks
+    pub in_degree: usize,
+    /// Number of (forward) links
+    pub out_degree: usize,
+    /// Normalised to be between 0 and 1
+    pub betweenness: f64,
+}
+
+/// The version of the JSON graph format, which is increased whenever the format changes
+const JSON_GRAPH_VERSION: u32 = 1;
+
+/// A graph in the node-link JSON format
+#[derive(Serialize)]
+struct JsonGraph<'a>
+{
+    version: u32,
+    directed: bool,
+    nodes: Vec<JsonNode<'a>>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+/// A note, or a tag, in the node-link JSON format
+#[derive(Serialize)]
+struct JsonNode<'a>
+{
+    /// The title, which is unique; with tags as nodes, it's prefixed by the type instead (`note:TITLE`
+    /// or `tag:TAG`), so that tags can't clash with notes
+    id: Cow<'a, str>,
+    /// `#TAG` for tags
+    title: Cow<'a, str>,
+    /// `note` or `tag`
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// `None` for ghosts and tags
+    project: Option<&'a str>,
+    tags: &'a [String],
+    ghost: bool,
+    /// Whether the note was only added around the results
+    context: bool,
+    in_degree: usize,
+    out_degree: usize,
+}
+
+/// A link in the node-link JSON format
+#[derive(Serialize)]
+struct JsonEdge<'a>
+{
+    /// `SOURCE -> TARGET`
+    id: String,
+    source: Cow<'a, str>,
+    target: Cow<'a, str>,
+    /// `link`, or `tag` if it goes from a note to one of its tags
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Whether the target doesn't exist
+    ghost: bool,
+}
+
+/// The way in which notes are grouped into clusters
+#[derive(Clone, Copy)]
+pub enum Clustering
+{
+    /// Notes that are connected by links, in any direction
+    Components,
+    /// Notes that are more densely linked to each other than to the rest, as found by the Louvain
+    /// method
+    Louvain,
+}
+
+/// A group of notes that belong together
+#[derive(Serialize)]
+pub struct Cluster
+{
+    /// Sorted alphabetically
+    pub members: Vec<String>,
+    /// The member with the highest PageRank
+    pub central: String,
+}
+
+/// Which notes are turned into a graph, given the query results
+#[derive(Clone, Copy)]
+pub enum Subgraph
+{
+    /// Only the results, and the links between them
+    Induced,
+    /// The results, along with every note (or ghost) they link to
+    Linked,
+    /// The results, along with every note that's at most this many hops away from them, the links
+    /// between all of them and their links to ghosts
+    Expanded(usize),
+}
+
+/// How the notes are drawn in a graph
+#[derive(Default)]
+pub struct GraphOptions
+{
+    /// Notes that were added around the results, and aren't part of them
+    pub context: HashSet<String>,
+    /// Whether the notes of every project are grouped into a subgraph
+    pub by_project: bool,
+    /// Whether every tag is added as a node, which the notes with that tag link to
+    pub tags: bool,
+    pub style: GraphStyle,
+    /// The titles of every note in the Zettelkasten, including those that aren't in the graph's
+    /// notes, so that the notes they link to aren't mistaken for ghosts; if `None`, then only the
+    /// graph's notes are known to exist
+    pub existing: Option<HashSet<String>>,
+}
+
+/// Return the dot format obtained from the graph made from the given Zettelkasten
+///
+/// Ghosts and the links to them are dashed, and context nodes are greyed out. Nodes are coloured
+/// and the notes of every project are grouped into a cluster as per `opts.style`, although
+/// `opts.by_project` always groups them.
+fn dot_output(zs: &[Zettel], opts: &GraphOptions) -> String
+{
+    let (nodes, edges) = attributed(zs, opts);
+    let style = &opts.style;
+
+    // every tag or project gets the next colour of the palette, in alphabetical order
+    let mut keys: Vec<&str> = nodes
+        .iter()
+        .filter_map(|n| color_key(n, style.color_by))
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+    let colors: HashMap<&str, &str> = keys
+        .into_iter()
+        .zip(style.palette.iter().cycle())
+        .map(|(k, c)| (k, c.as_str()))
+        .collect();
+
+    let node_line = |n: &JsonNode| {
+        let mut attrs = vec![];
+        let mut styles = vec![];
+        if n.id != n.title {
+            attrs.push(format!("label = \"{}\"", escape_dot(&n.title)));
+        }
+        if n.kind == "tag" {
+            attrs.push("shape = box".to_string());
+            styles.push("rounded");
+        }
+        if n.ghost {
+            styles.push("dashed");
+        } else if let Some(color) = color_key(n, style.color_by).and_then(|k| colors.get(k)) {
+            styles.push("filled");
+            attrs.push(format!("fillcolor = \"{}\"", escape_dot(color)));
+        }
+        if !styles.is_empty() {
+            attrs.push(format!("style = \"{}\"", styles.join(",")));
+        }
+        if n.context {
+            attrs.push("color = gray, fontcolor = gray".to_string());
+        }
+        if attrs.is_empty() {
+            format!("\"{}\";", escape_dot(&n.id))
+        } else {
+            format!("\"{}\" [{}];", escape_dot(&n.id), attrs.join(", "))
+        }
+    };
+
+    let mut lines = vec!["digraph {".to_string()];
+    if opts.by_project || style.clusters {
+        // the main Zettelkasten and ghosts don't belong to a project, so they're left outside
+        let mut projects: Vec<&str> = nodes
+            .iter()
+            .filter_map(|n| n.project)
+            .filter(|p| !p.is_empty())
+            .collect();
+        projects.sort_unstable();
+        projects.dedup();
+        for n in nodes.iter().filter(|n| n.project.is_none_or(str::is_empty)) {
+            lines.push(format!("    {}", node_line(n)));
+        }
+        for p in projects {
+            lines.push(format!("    subgraph \"cluster_{}\" {{", escape_dot(p)));
+            lines.push(format!("        label = \"{}\";", escape_dot(p)));
+            for n in nodes.iter().filter(|n| n.project == Some(p)) {
+                lines.push(format!("        {}", node_line(n)));
+            }
+            lines.push("    }".to_string());
+        }
+    } else {
+        for n in &nodes {
+            lines.push(format!("    {}", node_line(n)));
+        }
+    }
+    for e in &edges {
+        lines.push(format!(
+            "    \"{}\" -> \"{}\"{};",
+            escape_dot(&e.source),
+            escape_dot(&e.target),
+            if e.ghost {
+                " [style = dashed]"
+            } else if e.kind == "tag" {
+                " [style = dotted]"
+            } else {
+                ""
+            }
+        ));
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+}
 
 /// Print the dot format obtained from the graph made from the given Zettelkasten
-pub fn zk_graph_dot_output(zs: &[Zettel])
+pub fn zk_graph_dot_output(zs: &[Zettel], opts: &GraphOptions)
 {
-    dot_output(gen_graph(zs));
+    println!("{}", dot_output(zs, opts));
+}
+
+/// Return what the colour of a node depends on, if anything
+fn color_key<'n>(n: &'n JsonNode, color_by: ColorBy) -> Option<&'n str>
+{
+    match color_by {
+        ColorBy::None => None,
+        // tags take the colour of the notes they're the first tag of
+        ColorBy::Tag if n.kind == "tag" => n.title.strip_prefix('#'),
+        ColorBy::Tag => n.tags.first().map(|t| t.as_str()),
+        ColorBy::Project => n.project,
+    }
 }
 
 /// Print the JSON format obtained from the graph made from the given Zettelkasten
-pub fn zk_graph_json_output(zs: &[Zettel])
+pub fn zk_graph_json_output(zs: &[Zettel], opts: &GraphOptions)
+{
+    println!("{}", json_output(zs, opts));
+}
+
+/// Return the Zettel that make up the graph of the given query results, as per `mode`, along with
+/// the titles of the ones that were only added as context
+///
+/// The links of the returned Zettel only point to other returned Zettel, unless `mode` is
+/// `Subgraph::Linked`, or `Subgraph::Expanded`, which also keeps the links to ghosts.
+pub fn subgraph(
+    all: &[Zettel],
+    results: &[Zettel],
+    mode: Subgraph,
+) -> (Vec<Zettel>, HashSet<String>)
 {
-    println!("{}", json_output(gen_graph(zs)));
+    let (mut zs, context) = match mode {
+        Subgraph::Linked => return (results.to_vec(), HashSet::new()),
+        Subgraph::Induced => (results.to_vec(), HashSet::new()),
+        Subgraph::Expanded(hops) => {
+            let distances = neighbourhood(all, results, hops, Direction::Both);
+            let in_results: HashSet<&str> = results.iter().map(|z| z.title.as_str()).collect();
+            let mut zs = results.to_vec();
+            let mut context = HashSet::new();
+            for z in all {
+                if distances.contains_key(&z.title) && !in_results.contains(z.title.as_str()) {
+                    context.insert(z.title.clone());
+                    zs.push(z.clone());
+                }
+            }
+            (zs, context)
+        }
+    };
+
+    let titles: HashSet<String> = zs.iter().map(|z| z.title.clone()).collect();
+    // ghosts are never more than a hop away from the notes that link to them, so only the real
+    // notes outside of the neighbourhood are pruned when it's expanded
+    let real: Option<HashSet<&str>> = match mode {
+        Subgraph::Expanded(_) => Some(all.iter().map(|z| z.title.as_str()).collect()),
+        _ => None,
+    };
+    for z in &mut zs {
+        z.links.retain(|l| {
+            titles.contains(l) || real.as_ref().is_some_and(|r| !r.contains(l.as_str()))
+        });
+        z.backlinks.retain(|l| titles.contains(l));
+    }
+    (zs, context)
 }
 
 /// Turn a Zettelkasten into a directed graph, using petgraph
-fn gen_graph(zs: &[Zettel]) -> Graph<&str, &str>
+///
+/// Links are weighted with `ghost` if their target isn't in `existing`, or, if that's `None`, if
+/// it isn't one of the given Zettel.
+///
+/// Every note and every link is only looked at once, so this takes time proportional to the size
+/// of the Zettelkasten.
+fn gen_graph<'a>(zs: &'a [Zettel], existing: Option<&HashSet<String>>) -> Graph<&'a str, &'a str>
 {
-    let mut graph = Graph::<&str, &str>::new();
-    let mut idxs = vec![];
-    let titles: Vec<String> = zs.iter().map(|z| z.title.clone()).collect();
+    let link_count = zs.iter().map(|z| z.links.len()).sum();
+    let mut graph = Graph::<&str, &str>::with_capacity(zs.len(), link_count);
+    let titles: HashSet<&str> = zs.iter().map(|z| z.title.as_str()).collect();
+    let exists =
+        |title: &str| titles.contains(title) || existing.is_some_and(|e| e.contains(title));
+    // every entry should be added only once, so remember where each one was added
+    let mut seen: HashMap<&str, NodeIndex> = HashMap::with_capacity(zs.len());
+
     for z in zs {
-        // basically, figure out if we've seen the current Zettel, that is, if it's already
-        // added to the graph, because every entry should be added only once
-        let (_, seen_idx) = find_seen_by_name(idxs.clone(), &z.title);
-        let t_idx = if seen_idx == NodeIndex::default() {
-            let idx = graph.add_node(&z.title);
-            idxs.push((&z.title, idx));
-            idx
-        } else {
-            seen_idx
+        let title = z.title.as_str();
+        let t_idx = *seen.entry(title).or_insert_with(|| graph.add_node(title));
+        for l in &z.links {
+            let l_idx = *seen
+                .entry(l.as_str())
+                .or_insert_with(|| graph.add_node(l.as_str()));
+            let is_ghost = if exists(l) { "" } else { "ghost" };
+            graph.add_edge(t_idx, l_idx, is_ghost);
+        }
+    }
+    graph
+}
+
+/// Walk the Zettelkasten breadth-first, starting from every Zettel in `start`, and return the
+/// titles of all the Zettel that are at most `depth` hops away, along with their distance
+///
+/// Ghosts are never walked through, since they have no links of their own.
+pub fn neighbourhood(
+    zs: &[Zettel],
+    start: &[Zettel],
+    depth: usize,
+    direction: Direction,
+) -> HashMap<String, usize>
+{
+    let by_title: HashMap<&str, &Zettel> = zs.iter().map(|z| (z.title.as_str(), z)).collect();
+
+    let mut distances: HashMap<String, usize> = HashMap::new();
+    let mut queue: VecDeque<(&Zettel, usize)> = VecDeque::new();
+    for z in start {
+        if let Some(z) = by_title.get(z.title.as_str()) {
+            distances.insert(z.title.clone(), 0);
+            queue.push_back((z, 0));
+        }
+    }
+
+    while let Some((z, dist)) = queue.pop_front() {
+        if dist >= depth {
+            continue;
+        }
+        let outgoing = match direction {
+            Direction::In => [].iter(),
+            _ => z.links.iter(),
         };
+        let incoming = match direction {
+            Direction::Out => [].iter(),
+            _ => z.backlinks.iter(),
+        };
+        for title in outgoing.chain(incoming) {
+            if distances.contains_key(title) {
+                continue;
+            }
+            if let Some(next) = by_title.get(title.as_str()) {
+                distances.insert(title.clone(), dist + 1);
+                queue.push_back((next, dist + 1));
+            }
+        }
+    }
+
+    distances
+}
 
-        // the same is also checked for with every link
+/// Score every Zettel by how many neighbours it shares with the Zettel in `start`: one point for
+/// every note that links to both (co-citation), and one point for every note that both link to
+/// (bibliographic coupling)
+///
+/// The Zettel in `start` are left out, as are the Zettel that share no neighbours at all.
+pub fn related(zs: &[Zettel], start: &[Zettel]) -> HashMap<String, f64>
+{
+    let by_title: HashMap<&str, &Zettel> = zs.iter().map(|z| (z.title.as_str(), z)).collect();
+    // the backlinks of ghosts aren't stored anywhere, so they're all gathered here
+    let mut linked_from: HashMap<&str, Vec<&str>> = HashMap::new();
+    for z in zs {
         for l in &z.links {
-            let (_, seen_idx) = find_seen_by_name(idxs.clone(), l);
-            let l_idx = if seen_idx == NodeIndex::default() {
-                let idx = graph.add_node(l);
-                idxs.push((l, idx));
-                idx
+            linked_from.entry(l).or_default().push(&z.title);
+        }
+    }
+
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    for s in start {
+        let s = match by_title.get(s.title.as_str()) {
+            Some(s) => s,
+            None => continue,
+        };
+        // co-citation: the other notes linked to by the notes that link to this one
+        for b in &s.backlinks {
+            if let Some(b) = by_title.get(b.as_str()) {
+                for l in &b.links {
+                    *scores.entry(l.clone()).or_default() += 1.0;
+                }
+            }
+        }
+        // coupling: the other notes that link to the same notes as this one
+        for l in &s.links {
+            for other in linked_from.get(l.as_str()).into_iter().flatten() {
+                *scores.entry(other.to_string()).or_default() += 1.0;
+            }
+        }
+    }
+
+    for s in start {
+        scores.remove(&s.title);
+    }
+    scores.retain(|title, _| by_title.contains_key(title.as_str()));
+    scores
+}
+
+/// Compute the PageRank, degrees and betweenness centrality of every node in the graph of the given
+/// Zettelkasten, ghosts included
+///
+/// A node is a ghost if its title isn't in `existing`, or, if that's `None`, if it isn't one of the
+/// given Zettel.
+pub fn centrality(zs: &[Zettel], existing: Option<&HashSet<String>>) -> Vec<Centrality>
+{
+    let g = gen_graph(zs, existing);
+    let pageranks = pagerank(&g);
+    let betweenness = betweenness(&g);
+    g.node_indices()
+        .map(|n| Centrality {
+            title: g[n].to_string(),
+            ghost: g
+                .edges_directed(n, Incoming)
+                .next()
+                .is_some_and(|e| *e.weight() == "ghost"),
+            pagerank: pageranks[n.index()],
+            in_degree: g.edges_directed(n, Incoming).count(),
+            out_degree: g.edges_directed(n, Outgoing).count(),
+            betweenness: betweenness[n.index()],
+        })
+        .collect()
+}
+
+/// Group the notes of the given Zettelkasten into clusters, ghosts included, ignoring the direction
+/// of links, and return them from the biggest to the smallest
+///
+/// The notes that are linked to but aren't in `existing`, or, if that's `None`, aren't among the
+/// given Zettel, are ghosts.
+pub fn clusters(
+    zs: &[Zettel],
+    existing: Option<&HashSet<String>>,
+    method: Clustering,
+) -> Vec<Cluster>
+{
+    let g = gen_graph(zs, existing);
+    let n = g.node_count();
+    // the graph, undirected and weighted, where links in both directions count twice
+    let mut adjacency: Vec<HashMap<usize, f64>> = vec![HashMap::new(); n];
+    for e in g.edge_references() {
+        let (u, v) = (e.source().index(), e.target().index());
+        if u != v {
+            *adjacency[u].entry(v).or_default() += 1.0;
+            *adjacency[v].entry(u).or_default() += 1.0;
+        }
+    }
+
+    let membership = match method {
+        Clustering::Components => components(&adjacency),
+        Clustering::Louvain => louvain(&adjacency),
+    };
+
+    let pageranks = pagerank(&g);
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (node, community) in membership.iter().enumerate() {
+        groups.entry(*community).or_default().push(node);
+    }
+    let mut clusters: Vec<Cluster> = groups
+        .into_values()
+        .map(|nodes| {
+            let central = nodes
+                .iter()
+                .max_by(|a, b| {
+                    pageranks[**a]
+                        .total_cmp(&pageranks[**b])
+                        .then(g[NodeIndex::new(**b)].cmp(g[NodeIndex::new(**a)]))
+                })
+                .map(|c| g[NodeIndex::new(*c)].to_string())
+                .unwrap_or_default();
+            let mut members: Vec<String> = nodes
+                .iter()
+                .map(|m| g[NodeIndex::new(*m)].to_string())
+                .collect();
+            members.sort();
+            Cluster { members, central }
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then(a.members.cmp(&b.members))
+    });
+    clusters
+}
+
+/// Return the connected component of every node of the undirected graph, as the index of one of its
+/// nodes
+fn components(adjacency: &[HashMap<usize, f64>]) -> Vec<usize>
+{
+    let mut membership: Vec<Option<usize>> = vec![None; adjacency.len()];
+    for start in 0..adjacency.len() {
+        if membership[start].is_some() {
+            continue;
+        }
+        membership[start] = Some(start);
+        let mut stack = vec![start];
+        while let Some(v) = stack.pop() {
+            for w in adjacency[v].keys() {
+                if membership[*w].is_none() {
+                    membership[*w] = Some(start);
+                    stack.push(*w);
+                }
+            }
+        }
+    }
+    membership
+        .into_iter()
+        .map(|m| m.unwrap_or_default())
+        .collect()
+}
+
+/// Return the community of every node of the undirected, weighted graph, as found by the Louvain
+/// method: every node is moved to the community of a neighbour for as long as that increases the
+/// modularity, then every community is merged into a single node, and so on, until nothing changes
+fn louvain(adjacency: &[HashMap<usize, f64>]) -> Vec<usize>
+{
+    // the community of every original node, as a node of the current (merged) graph
+    let mut membership: Vec<usize> = (0..adjacency.len()).collect();
+    let mut adjacency = adjacency.to_vec();
+    let total: f64 = adjacency.iter().flat_map(|a| a.values()).sum();
+    if total == 0.0 {
+        return membership;
+    }
+
+    loop {
+        let n = adjacency.len();
+        let degrees: Vec<f64> = adjacency.iter().map(|a| a.values().sum()).collect();
+        let mut community: Vec<usize> = (0..n).collect();
+        let mut community_degrees = degrees.clone();
+
+        let mut improved = false;
+        loop {
+            let mut moved = false;
+            for v in 0..n {
+                let current = community[v];
+                community_degrees[current] -= degrees[v];
+
+                let mut weights: HashMap<usize, f64> = HashMap::new();
+                for (w, weight) in &adjacency[v] {
+                    if *w != v {
+                        *weights.entry(community[*w]).or_default() += weight;
+                    }
+                }
+                let gain =
+                    |c: usize, weight: f64| weight - community_degrees[c] * degrees[v] / total;
+                let mut best = current;
+                let mut best_gain = gain(current, weights.get(&current).copied().unwrap_or(0.0));
+                let mut candidates: Vec<(&usize, &f64)> = weights.iter().collect();
+                candidates.sort_by_key(|(c, _)| **c);
+                for (c, weight) in candidates {
+                    let g = gain(*c, *weight);
+                    if g > best_gain + 1e-12 {
+                        best = *c;
+                        best_gain = g;
+                    }
+                }
+
+                community[v] = best;
+                community_degrees[best] += degrees[v];
+                if best != current {
+                    moved = true;
+                    improved = true;
+                }
+            }
+            if !moved {
+                break;
+            }
+        }
+        if !improved {
+            break;
+        }
+
+        // merge every community into a single node
+        let mut renumbered: HashMap<usize, usize> = HashMap::new();
+        for c in &community {
+            let next = renumbered.len();
+            renumbered.entry(*c).or_insert(next);
+        }
+        let mut merged: Vec<HashMap<usize, f64>> = vec![HashMap::new(); renumbered.len()];
+        for (v, neighbours) in adjacency.iter().enumerate() {
+            for (w, weight) in neighbours {
+                *merged[renumbered[&community[v]]]
+                    .entry(renumbered[&community[*w]])
+                    .or_default() += weight;
+            }
+        }
+        for m in membership.iter_mut() {
+            *m = renumbered[&community[*m]];
+        }
+        adjacency = merged;
+    }
+
+    membership
+}
+
+/// Return the strongly connected components of the graph of the given Zettelkasten, i.e. the groups
+/// of notes in which every note can be reached from every other note by following links, from the
+/// biggest to the smallest
+///
+/// Notes that are in no cycle at all are left out. The notes that are linked to but aren't in
+/// `existing`, or, if that's `None`, aren't among the given Zettel, are ghosts.
+pub fn strongly_connected(zs: &[Zettel], existing: Option<&HashSet<String>>) -> Vec<Vec<String>>
+{
+    let g = gen_graph(zs, existing);
+    let mut components: Vec<Vec<String>> = tarjan_scc(&g)
+        .into_iter()
+        .filter(|c| c.len() > 1 || g.contains_edge(c[0], c[0]))
+        .map(|c| {
+            let mut titles: Vec<String> = c.iter().map(|n| g[*n].to_string()).collect();
+            titles.sort();
+            titles
+        })
+        .collect();
+    components.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    components
+}
+
+/// Return the elementary cycles of the graph of the given Zettelkasten that are made up of at most
+/// `max_length` notes, i.e. the chains of links that lead back to where they started without going
+/// through any note twice, from the shortest to the longest
+///
+/// Every cycle starts with its alphabetically first note, which isn't repeated at the end. The notes
+/// that are linked to but aren't in `existing`, or, if that's `None`, aren't among the given
+/// Zettel, are ghosts.
+pub fn cycles(
+    zs: &[Zettel],
+    existing: Option<&HashSet<String>>,
+    max_length: usize,
+) -> Vec<Vec<String>>
+{
+    let g = gen_graph(zs, existing);
+    let mut cycles: Vec<Vec<String>> = vec![];
+    for component in tarjan_scc(&g) {
+        let mut component = component;
+        component.sort();
+        // every cycle is only found from its node with the lowest index
+        for (i, start) in component.iter().enumerate() {
+            let allowed = &component[i..];
+            let mut path = vec![*start];
+            cycles_from(&g, allowed, max_length, &mut path, &mut |cycle| {
+                let mut titles: Vec<String> = cycle.iter().map(|n| g[*n].to_string()).collect();
+                let first = (0..titles.len()).min_by_key(|t| &titles[*t]).unwrap_or(0);
+                titles.rotate_left(first);
+                cycles.push(titles);
+            });
+        }
+    }
+    cycles.sort_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)));
+    cycles
+}
+
+/// Extend the path by following links through the allowed nodes, calling `found` with every path
+/// that leads back to its first node
+fn cycles_from(
+    g: &Graph<&str, &str>,
+    allowed: &[NodeIndex],
+    max_length: usize,
+    path: &mut Vec<NodeIndex>,
+    found: &mut dyn FnMut(&[NodeIndex]),
+)
+{
+    let last = path[path.len() - 1];
+    let mut next: Vec<NodeIndex> = g.neighbors_directed(last, Outgoing).collect();
+    next.sort();
+    next.dedup();
+    for n in next {
+        if n == path[0] {
+            found(path);
+        } else if path.len() < max_length && allowed.binary_search(&n).is_ok() && !path.contains(&n)
+        {
+            path.push(n);
+            cycles_from(g, allowed, max_length, path, found);
+            path.pop();
+        }
+    }
+}
+
+/// Print the given groups of notes as a DOT graph, where every group is a subgraph that contains
+/// the links between its own notes; if `chains` is set, then every group is a chain of notes that
+/// leads back to the first one, and only those links are included
+///
+/// A note may belong to several groups, so it's repeated in every one of them.
+pub fn subgraphs_dot_output(zs: &[Zettel], groups: &[Vec<String>], name: &str, chains: bool)
+{
+    let links: HashMap<&str, &[String]> = zs
+        .iter()
+        .map(|z| (z.title.as_str(), z.links.as_slice()))
+        .collect();
+
+    println!("digraph {{");
+    for (i, group) in groups.iter().enumerate() {
+        println!("    subgraph cluster_{} {{", i);
+        println!("        label = \"{} {}\";", name, i + 1);
+        for title in group {
+            println!(
+                "        \"{}:{}\" [label=\"{}\"];",
+                i,
+                escape_dot(title),
+                escape_dot(title)
+            );
+        }
+        let edges: Vec<(&String, &String)> = if chains {
+            group.iter().zip(group.iter().cycle().skip(1)).collect()
+        } else {
+            group
+                .iter()
+                .flat_map(|from| {
+                    let targets = links.get(from.as_str()).copied().unwrap_or_default();
+                    group
+                        .iter()
+                        .filter(move |to| targets.contains(to))
+                        .map(move |to| (from, to))
+                })
+                .collect()
+        };
+        for (from, to) in edges {
+            println!(
+                "        \"{}:{}\" -> \"{}:{}\";",
+                i,
+                escape_dot(from),
+                i,
+                escape_dot(to)
+            );
+        }
+        println!("    }}");
+    }
+    println!("}}");
+}
+
+/// Compute the PageRank of every node, indexed by node index
+///
+/// Notes without links are treated as if they linked to every note, so that the scores always add
+/// up to 1.
+fn pagerank(g: &Graph<&str, &str>) -> Vec<f64>
+{
+    let n = g.node_count();
+    if n == 0 {
+        return vec![];
+    }
+    let out_degrees: Vec<usize> = g
+        .node_indices()
+        .map(|v| g.edges_directed(v, Outgoing).count())
+        .collect();
+
+    let mut ranks = vec![1.0 / n as f64; n];
+    for _ in 0..PAGERANK_ITERATIONS {
+        let dangling: f64 = ranks
+            .iter()
+            .zip(&out_degrees)
+            .filter(|(_, d)| **d == 0)
+            .map(|(r, _)| r)
+            .sum();
+        let base = (1.0 - DAMPING) / n as f64 + DAMPING * dangling / n as f64;
+        let mut next = vec![base; n];
+        for e in g.edge_references() {
+            let (u, v) = (e.source().index(), e.target().index());
+            next[v] += DAMPING * ranks[u] / out_degrees[u] as f64;
+        }
+        let change: f64 = next.iter().zip(&ranks).map(|(a, b)| (a - b).abs()).sum();
+        ranks = next;
+        if change < PAGERANK_TOLERANCE {
+            break;
+        }
+    }
+    ranks
+}
+
+/// Compute the betweenness centrality of every node, indexed by node index, i.e. the share of
+/// shortest paths between two other nodes that go through it, following links in their direction
+///
+/// This is Brandes' algorithm, run from every node in parallel.
+fn betweenness(g: &Graph<&str, &str>) -> Vec<f64>
+{
+    let n = g.node_count();
+    let totals = g
+        .node_indices()
+        .collect::<Vec<NodeIndex>>()
+        .par_iter()
+        .map(|s| {
+            // the number of shortest paths from `s` to every node, their length, and the nodes
+            // that come right before on them
+            let mut paths = vec![0.0; n];
+            let mut distances: Vec<Option<usize>> = vec![None; n];
+            let mut predecessors: Vec<Vec<usize>> = vec![vec![]; n];
+            let mut visited = vec![];
+            let mut queue = VecDeque::new();
+            paths[s.index()] = 1.0;
+            distances[s.index()] = Some(0);
+            queue.push_back(*s);
+            while let Some(v) = queue.pop_front() {
+                visited.push(v.index());
+                let dist = distances[v.index()].unwrap_or_default();
+                for w in g.neighbors_directed(v, Outgoing) {
+                    let w_i = w.index();
+                    if distances[w_i].is_none() {
+                        distances[w_i] = Some(dist + 1);
+                        queue.push_back(w);
+                    }
+                    if distances[w_i] == Some(dist + 1) {
+                        paths[w_i] += paths[v.index()];
+                        predecessors[w_i].push(v.index());
+                    }
+                }
+            }
+
+            // walk back from the farthest nodes, accumulating the dependencies of `s` on every
+            // node
+            let mut dependencies = vec![0.0; n];
+            for &w in visited.iter().rev() {
+                for &v in &predecessors[w] {
+                    dependencies[v] += paths[v] / paths[w] * (1.0 + dependencies[w]);
+                }
+            }
+            dependencies[s.index()] = 0.0;
+            dependencies
+        })
+        .reduce(
+            || vec![0.0; n],
+            |a, b| a.iter().zip(&b).map(|(x, y)| x + y).collect(),
+        );
+
+    let pairs = if n > 2 {
+        ((n - 1) * (n - 2)) as f64
+    } else {
+        1.0
+    };
+    totals.into_iter().map(|b| b / pairs).collect()
+}
+
+/// Find the `k` shortest paths (or less, if there aren't that many) that go from `from` to `to`,
+/// by following links between the given Zettel. If `undirected` is set, then backlinks may also be
+/// followed.
+///
+/// Paths are returned as lists of titles, from the shortest to the longest.
+pub fn shortest_paths(
+    zs: &[Zettel],
+    from: &str,
+    to: &str,
+    k: usize,
+    undirected: bool,
+) -> Vec<Vec<String>>
+{
+    let mut g = gen_graph(zs, None);
+    if undirected {
+        let reversed: Vec<_> = g
+            .edge_references()
+            .map(|e| (e.target(), e.source(), *e.weight()))
+            .collect();
+        for (source, target, weight) in reversed {
+            g.add_edge(source, target, weight);
+        }
+    }
+
+    let (start, goal) = match (find_node(&g, from), find_node(&g, to)) {
+        (Some(start), Some(goal)) => (start, goal),
+        _ => return vec![],
+    };
+
+    // Yen's algorithm: every path after the first one is the shortest deviation from one of the
+    // paths found before it
+    let mut found: Vec<Vec<NodeIndex>> = vec![];
+    let mut candidates: Vec<Vec<NodeIndex>> = vec![];
+    if let Some((_, path)) = astar(&g, start, |n| n == goal, |_| 1, |_| 0) {
+        found.push(path);
+    }
+    while !found.is_empty() && found.len() < k {
+        let previous = found.last().unwrap().clone();
+        for i in 0..previous.len() - 1 {
+            let spur = previous[i];
+            let root = &previous[..=i];
+
+            // don't take the same detour twice, and don't loop back into the root path
+            let removed_edges: Vec<(NodeIndex, NodeIndex)> = found
+                .iter()
+                .filter(|p| p.len() > i + 1 && &p[..=i] == root)
+                .map(|p| (p[i], p[i + 1]))
+                .collect();
+            let removed_nodes = &root[..i];
+            let filtered = EdgeFiltered::from_fn(&g, |e| {
+                !removed_edges.contains(&(e.source(), e.target()))
+                    && !removed_nodes.contains(&e.source())
+                    && !removed_nodes.contains(&e.target())
+            });
+
+            if let Some((_, spur_path)) = astar(&filtered, spur, |n| n == goal, |_| 1, |_| 0) {
+                let mut path = root[..i].to_vec();
+                path.extend(spur_path);
+                if !found.contains(&path) && !candidates.contains(&path) {
+                    candidates.push(path);
+                }
+            }
+        }
+        if candidates.is_empty() {
+            break;
+        }
+        candidates.sort_by_key(|p| p.len());
+        found.push(candidates.remove(0));
+    }
+
+    found
+        .into_iter()
+        .map(|p| p.into_iter().map(|n| g[n].to_string()).collect())
+        .collect()
+}
+
+/// Return the Zettel that make up the given paths, keeping only the links that are walked through
+/// by said paths. Ghosts are left out, since the links pointing to them are enough to add them to a
+/// graph.
+pub fn paths_to_zettel(zs: &[Zettel], paths: &[Vec<String>]) -> Vec<Zettel>
+{
+    let mut on_path: Vec<Zettel> = zs
+        .iter()
+        .filter(|z| paths.iter().any(|p| p.contains(&z.title)))
+        .map(|z| Zettel {
+            links: vec![],
+            ..z.clone()
+        })
+        .collect();
+
+    for path in paths {
+        for pair in path.windows(2) {
+            // the link may have been walked through backwards, so check which note it belongs to
+            for (source, target) in [(&pair[0], &pair[1]), (&pair[1], &pair[0])] {
+                let linked = zs
+                    .iter()
+                    .any(|z| &z.title == source && z.links.contains(target));
+                if let Some(z) = on_path.iter_mut().find(|z| &z.title == source) {
+                    if linked && !z.links.contains(target) {
+                        z.links.push(target.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    on_path
+}
+
+/// Return the index of the node with the given name, if there's one
+fn find_node(g: &Graph<&str, &str>, name: &str) -> Option<NodeIndex>
+{
+    g.node_indices().find(|i| g[*i] == name)
+}
+
+/// Turn the graph made from the given Zettelkasten into its nodes and edges, along with their
+/// attributes, sorted by their IDs so that the output only changes when the Zettelkasten does
+fn attributed<'a>(zs: &'a [Zettel], opts: &GraphOptions) -> (Vec<JsonNode<'a>>, Vec<JsonEdge<'a>>)
+{
+    let g = gen_graph(zs, opts.existing.as_ref());
+    let by_title: HashMap<&str, &Zettel> = zs.iter().map(|z| (z.title.as_str(), z)).collect();
+
+    let mut nodes: Vec<JsonNode> = g
+        .node_indices()
+        .map(|n| {
+            let z = by_title.get(g[n]);
+            JsonNode {
+                id: Cow::Borrowed(g[n]),
+                title: Cow::Borrowed(g[n]),
+                kind: "note",
+                project: z.map(|z| z.project.as_str()),
+                tags: z.map_or(&[], |z| z.tags.as_slice()),
+                // notes outside of the results still exist, even if only their title is known
+                ghost: z.is_none() && !opts.existing.as_ref().is_some_and(|e| e.contains(g[n])),
+                context: opts.context.contains(g[n]),
+                in_degree: g.edges_directed(n, Incoming).count(),
+                out_degree: g.edges_directed(n, Outgoing).count(),
+            }
+        })
+        .collect();
+
+    let mut edges: Vec<JsonEdge> = g
+        .edge_references()
+        .map(|e| JsonEdge {
+            id: format!("{} -> {}", g[e.source()], g[e.target()]),
+            source: Cow::Borrowed(g[e.source()]),
+            target: Cow::Borrowed(g[e.target()]),
+            kind: "link",
+            ghost: *e.weight() == "ghost",
+        })
+        .collect();
+
+    if opts.tags {
+        // a note may well be called `#TAG`, so every ID is prefixed by what it's the ID of
+        for n in &mut nodes {
+            if let Some(z) = by_title.get(n.id.as_ref()) {
+                n.out_degree += z.tags.len();
+            }
+            n.id = Cow::Owned(format!("note:{}", n.id));
+        }
+        for e in &mut edges {
+            e.source = Cow::Owned(format!("note:{}", e.source));
+            e.target = Cow::Owned(format!("note:{}", e.target));
+            e.id = format!("{} -> {}", e.source, e.target);
+        }
+
+        // ghosts have no tags, so only the Zettel themselves are looked at
+        let mut tagged: HashMap<&str, usize> = HashMap::new();
+        for z in zs {
+            for t in &z.tags {
+                *tagged.entry(t.as_str()).or_default() += 1;
+                edges.push(JsonEdge {
+                    id: format!("note:{} -> tag:{}", z.title, t),
+                    source: Cow::Owned(format!("note:{}", z.title)),
+                    target: Cow::Owned(format!("tag:{}", t)),
+                    kind: "tag",
+                    ghost: false,
+                });
+            }
+        }
+        nodes.extend(tagged.into_iter().map(|(t, count)| JsonNode {
+            id: Cow::Owned(format!("tag:{}", t)),
+            title: Cow::Owned(format!("#{}", t)),
+            kind: "tag",
+            project: None,
+            tags: &[],
+            ghost: false,
+            context: false,
+            in_degree: count,
+            out_degree: 0,
+        }));
+    }
+
+    nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    edges.sort_by(|a, b| (&a.source, &a.target).cmp(&(&b.source, &b.target)));
+
+    (nodes, edges)
+}
+
+/// Turn the graph made from the given Zettelkasten into the node-link JSON format (see
+/// `doc/graphs.md`)
+fn json_output(zs: &[Zettel], opts: &GraphOptions) -> String
+{
+    let (nodes, edges) = attributed(zs, opts);
+    serde_json::to_string(&JsonGraph {
+        version: JSON_GRAPH_VERSION,
+        directed: true,
+        nodes,
+        edges,
+    })
+    .unwrap()
+}
+
+/// Print the GraphML format obtained from the graph made from the given Zettelkasten
+pub fn zk_graph_graphml_output(zs: &[Zettel], opts: &GraphOptions)
+{
+    let (nodes, edges) = attributed(zs, opts);
+    println!(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    println!(r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#);
+    for (id, target, name, kind) in [
+        ("title", "node", "title", "string"),
+        ("type", "node", "type", "string"),
+        ("project", "node", "project", "string"),
+        ("tags", "node", "tags", "string"),
+        ("ghost", "node", "ghost", "boolean"),
+        ("context", "node", "context", "boolean"),
+        ("in_degree", "node", "in_degree", "int"),
+        ("out_degree", "node", "out_degree", "int"),
+        ("edge_type", "edge", "type", "string"),
+        ("edge_ghost", "edge", "ghost", "boolean"),
+    ] {
+        println!(
+            r#"  <key id="{}" for="{}" attr.name="{}" attr.type="{}"/>"#,
+            id, target, name, kind
+        );
+    }
+    println!(r#"  <graph id="zettelkasten" edgedefault="directed">"#);
+    for n in &nodes {
+        println!(r#"    <node id="{}">"#, escape_xml(&n.id));
+        println!(r#"      <data key="title">{}</data>"#, escape_xml(&n.title));
+        println!(r#"      <data key="type">{}</data>"#, n.kind);
+        // ghosts don't belong to any project, so they don't have one at all
+        if let Some(project) = n.project {
+            println!(
+                r#"      <data key="project">{}</data>"#,
+                escape_xml(project)
+            );
+        }
+        println!(
+            r#"      <data key="tags">{}</data>"#,
+            escape_xml(&n.tags.join("|"))
+        );
+        println!(r#"      <data key="ghost">{}</data>"#, n.ghost);
+        println!(r#"      <data key="context">{}</data>"#, n.context);
+        println!(r#"      <data key="in_degree">{}</data>"#, n.in_degree);
+        println!(r#"      <data key="out_degree">{}</data>"#, n.out_degree);
+        println!("    </node>");
+    }
+    for e in &edges {
+        println!(
+            r#"    <edge id="{}" source="{}" target="{}">"#,
+            escape_xml(&e.id),
+            escape_xml(&e.source),
+            escape_xml(&e.target)
+        );
+        println!(r#"      <data key="edge_type">{}</data>"#, e.kind);
+        println!(r#"      <data key="edge_ghost">{}</data>"#, e.ghost);
+        println!("    </edge>");
+    }
+    println!("  </graph>");
+    println!("</graphml>");
+}
+
+/// Print the GEXF format obtained from the graph made from the given Zettelkasten
+pub fn zk_graph_gexf_output(zs: &[Zettel], opts: &GraphOptions)
+{
+    let (nodes, edges) = attributed(zs, opts);
+    println!(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    println!(r#"<gexf xmlns="http://www.gexf.net/1.2draft" version="1.2">"#);
+    println!("  <meta>");
+    println!("    <creator>settle</creator>");
+    println!("  </meta>");
+    println!(r#"  <graph mode="static" defaultedgetype="directed">"#);
+    println!(r#"    <attributes class="node">"#);
+    for (id, kind) in [
+        ("type", "string"),
+        ("project", "string"),
+        ("tags", "string"),
+        ("ghost", "boolean"),
+        ("context", "boolean"),
+        ("in_degree", "integer"),
+        ("out_degree", "integer"),
+    ] {
+        println!(
+            r#"      <attribute id="{}" title="{}" type="{}"/>"#,
+            id, id, kind
+        );
+    }
+    println!("    </attributes>");
+    println!(r#"    <attributes class="edge">"#);
+    println!(r#"      <attribute id="type" title="type" type="string"/>"#);
+    println!(r#"      <attribute id="ghost" title="ghost" type="boolean"/>"#);
+    println!("    </attributes>");
+    println!("    <nodes>");
+    for n in &nodes {
+        println!(
+            r#"      <node id="{}" label="{}">"#,
+            escape_xml(&n.id),
+            escape_xml(&n.title)
+        );
+        println!("        <attvalues>");
+        println!(r#"          <attvalue for="type" value="{}"/>"#, n.kind);
+        if let Some(project) = n.project {
+            println!(
+                r#"          <attvalue for="project" value="{}"/>"#,
+                escape_xml(project)
+            );
+        }
+        println!(
+            r#"          <attvalue for="tags" value="{}"/>"#,
+            escape_xml(&n.tags.join("|"))
+        );
+        println!(r#"          <attvalue for="ghost" value="{}"/>"#, n.ghost);
+        println!(
+            r#"          <attvalue for="context" value="{}"/>"#,
+            n.context
+        );
+        println!(
+            r#"          <attvalue for="in_degree" value="{}"/>"#,
+            n.in_degree
+        );
+        println!(
+            r#"          <attvalue for="out_degree" value="{}"/>"#,
+            n.out_degree
+        );
+        println!("        </attvalues>");
+        println!("      </node>");
+    }
+    println!("    </nodes>");
+    println!("    <edges>");
+    for e in &edges {
+        println!(
+            r#"      <edge id="{}" source="{}" target="{}">"#,
+            escape_xml(&e.id),
+            escape_xml(&e.source),
+            escape_xml(&e.target)
+        );
+        println!("        <attvalues>");
+        println!(r#"          <attvalue for="type" value="{}"/>"#, e.kind);
+        println!(r#"          <attvalue for="ghost" value="{}"/>"#, e.ghost);
+        println!("        </attvalues>");
+        println!("      </edge>");
+    }
+    println!("    </edges>");
+    println!("  </graph>");
+    println!("</gexf>");
+}
+
+/// Print the nodes of the graph made from the given Zettelkasten as comma-separated values,
+/// preceded by a header
+pub fn zk_graph_csv_nodes_output(zs: &[Zettel], opts: &GraphOptions)
+{
+    let (nodes, _) = attributed(zs, opts);
+    println!("id,title,type,project,tags,ghost,context,in_degree,out_degree");
+    for n in &nodes {
+        println!(
+            "{},{},{},{},{},{},{},{},{}",
+            escape_csv(&n.id),
+            escape_csv(&n.title),
+            n.kind,
+            escape_csv(n.project.unwrap_or_default()),
+            escape_csv(&n.tags.join("|")),
+            n.ghost,
+            n.context,
+            n.in_degree,
+            n.out_degree
+        );
+    }
+}
+
+/// Print the edges of the graph made from the given Zettelkasten as comma-separated values,
+/// preceded by a header
+pub fn zk_graph_csv_edges_output(zs: &[Zettel], opts: &GraphOptions)
+{
+    let (_, edges) = attributed(zs, opts);
+    println!("id,source,target,type,ghost");
+    for e in &edges {
+        println!(
+            "{},{},{},{},{}",
+            escape_csv(&e.id),
+            escape_csv(&e.source),
+            escape_csv(&e.target),
+            e.kind,
+            e.ghost
+        );
+    }
+}
+
+/// Print a Mermaid flowchart obtained from the graph made from the given Zettelkasten, in a fenced
+/// code block that can be pasted into a Markdown note
+///
+/// Ghosts are dashed, and so are the links to them, context nodes are greyed out, and tags are
+/// drawn as stadiums. If
+/// `opts.by_project` is true, then the notes of every project are grouped into a subgraph.
+pub fn zk_graph_mermaid_output(zs: &[Zettel], opts: &GraphOptions)
+{
+    let (nodes, edges) = attributed(zs, opts);
+    println!("```mermaid");
+    println!("flowchart LR");
+    println!("    classDef ghost stroke-dasharray: 5 5, color: #888");
+    println!("    classDef context fill: #eee, stroke: #aaa, color: #888");
+
+    let node_line = |n: &JsonNode| {
+        // tags are drawn as stadiums, to set them apart from the notes
+        let (open, close) = if n.kind == "tag" {
+            ("([", "])")
+        } else {
+            ("[", "]")
+        };
+        format!(
+            r#"{}{}"{}"{}{}"#,
+            mermaid_id("n", &n.id),
+            open,
+            escape_mermaid(&n.title),
+            close,
+            if n.ghost {
+                ":::ghost"
+            } else if n.context {
+                ":::context"
             } else {
-                seen_idx
-            };
-            let is_ghost = if titles.contains(l) { "" } else { "ghost" };
-            graph.add_edge(t_idx, l_idx, is_ghost);
+                ""
+            }
+        )
+    };
+    if opts.by_project {
+        // the main Zettelkasten and ghosts don't belong to a project, so they're left outside
+        let mut projects: Vec<&str> = nodes
+            .iter()
+            .filter_map(|n| n.project)
+            .filter(|p| !p.is_empty())
+            .collect();
+        projects.sort_unstable();
+        projects.dedup();
+        for n in nodes.iter().filter(|n| n.project.is_none_or(str::is_empty)) {
+            println!("    {}", node_line(n));
+        }
+        for p in projects {
+            println!(
+                r#"    subgraph {}["{}"]"#,
+                mermaid_id("p", p),
+                escape_mermaid(p)
+            );
+            for n in nodes.iter().filter(|n| n.project == Some(p)) {
+                println!("        {}", node_line(n));
+            }
+            println!("    end");
+        }
+    } else {
+        for n in &nodes {
+            println!("    {}", node_line(n));
         }
     }
-    graph
+
+    for e in &edges {
+        println!(
+            "    {} {} {}",
+            mermaid_id("n", &e.source),
+            if e.ghost {
+                "-.->"
+            } else if e.kind == "tag" {
+                "---"
+            } else {
+                "-->"
+            },
+            mermaid_id("n", &e.target)
+        );
+    }
+    println!("```");
 }
 
-/// Given a list of seen names and their index in the graph, return the pair that matches the name
-/// of the node
-fn find_seen_by_name(seen: Vec<(&str, NodeIndex)>, name: &str) -> (String, NodeIndex)
+/// Print a standalone SVG image of the graph made from the given Zettelkasten, laid out by
+/// simulating forces between the nodes, so that no external renderer is needed
+///
+/// Every node is labelled with its title, and hovering over it shows the title as well. Ghosts and
+/// the links to them are dashed, context nodes are greyed out, tags are squares, and nodes are
+/// coloured as per `opts.style`.
+pub fn zk_graph_svg_output(zs: &[Zettel], opts: &GraphOptions)
 {
-    let (n, i) = seen
+    let (nodes, edges) = attributed(zs, opts);
+    let style = &opts.style;
+
+    let indices: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (n.id.as_ref(), i))
+        .collect();
+    let pairs: Vec<(usize, usize)> = edges
+        .iter()
+        .map(|e| (indices[e.source.as_ref()], indices[e.target.as_ref()]))
+        .collect();
+    let positions = force_layout(nodes.len(), &pairs);
+
+    let mut keys: Vec<&str> = nodes
+        .iter()
+        .filter_map(|n| color_key(n, style.color_by))
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+    let colors: HashMap<&str, &str> = keys
         .into_iter()
-        .find(|(v, _)| v == &name)
-        .unwrap_or_default();
-    (n.to_string(), i)
+        .zip(style.palette.iter().cycle())
+        .map(|(k, c)| (k, c.as_str()))
+        .collect();
+
+    // make room for the labels, which are roughly 7 pixels per character wide
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+    for (n, &(x, y)) in nodes.iter().zip(&positions) {
+        let half_width = (n.title.chars().count() as f64 * 3.5).max(NODE_RADIUS);
+        min_x = min_x.min(x - half_width);
+        max_x = max_x.max(x + half_width);
+        min_y = min_y.min(y - NODE_RADIUS);
+        max_y = max_y.max(y + NODE_RADIUS + 16.0);
+    }
+    let margin = 10.0;
+    let (width, height) = (max_x - min_x + 2.0 * margin, max_y - min_y + 2.0 * margin);
+
+    println!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{:.0}" height="{:.0}" viewBox="{:.1} {:.1} {:.1} {:.1}" font-family="sans-serif" font-size="12">"#,
+        width,
+        height,
+        min_x - margin,
+        min_y - margin,
+        width,
+        height
+    );
+    println!("  <defs>");
+    println!(
+        r##"    <marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M 0 0 L 10 5 L 0 10 z" fill="#888"/></marker>"##
+    );
+    println!("  </defs>");
+    println!(
+        r#"  <rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="white"/>"#,
+        min_x - margin,
+        min_y - margin,
+        width,
+        height
+    );
+
+    println!(r##"  <g stroke="#888" stroke-width="1">"##);
+    for (e, &(u, v)) in edges.iter().zip(&pairs) {
+        let ((x1, y1), (x2, y2)) = (positions[u], positions[v]);
+        let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        // notes that link to themselves, or that are on top of each other, have nothing to draw
+        if length <= 2.0 * NODE_RADIUS {
+            continue;
+        }
+        // the arrowhead should touch the edge of the target, not its centre
+        let (dx, dy) = ((x2 - x1) / length, (y2 - y1) / length);
+        let attrs = if e.ghost {
+            r#" stroke-dasharray="4 3" marker-end="url(#arrow)""#
+        } else if e.kind == "tag" {
+            r#" stroke-dasharray="1 3""#
+        } else {
+            r#" marker-end="url(#arrow)""#
+        };
+        println!(
+            r#"    <line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"{}/>"#,
+            x1 + dx * NODE_RADIUS,
+            y1 + dy * NODE_RADIUS,
+            x2 - dx * NODE_RADIUS,
+            y2 - dy * NODE_RADIUS,
+            attrs
+        );
+    }
+    println!("  </g>");
+
+    println!(r##"  <g stroke="#333" stroke-width="1" text-anchor="middle">"##);
+    for (n, &(x, y)) in nodes.iter().zip(&positions) {
+        let fill = if n.ghost {
+            "white"
+        } else if n.context {
+            "#ddd"
+        } else {
+            color_key(n, style.color_by)
+                .and_then(|k| colors.get(k).copied())
+                .unwrap_or(if n.kind == "tag" {
+                    "#e69f00"
+                } else {
+                    "#80b1d3"
+                })
+        };
+        let shape_attrs = format!(
+            r#"fill="{}"{}"#,
+            escape_xml(fill),
+            if n.ghost {
+                r#" stroke-dasharray="2 2""#
+            } else {
+                ""
+            }
+        );
+        let title = escape_xml(&n.title);
+        println!("    <g>");
+        println!("      <title>{}</title>", title);
+        if n.kind == "tag" {
+            println!(
+                r#"      <rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" {}/>"#,
+                x - NODE_RADIUS,
+                y - NODE_RADIUS,
+                2.0 * NODE_RADIUS,
+                2.0 * NODE_RADIUS,
+                shape_attrs
+            );
+        } else {
+            println!(
+                r#"      <circle cx="{:.1}" cy="{:.1}" r="{:.1}" {}/>"#,
+                x, y, NODE_RADIUS, shape_attrs
+            );
+        }
+        println!(
+            r#"      <text x="{:.1}" y="{:.1}" stroke="none" fill="{}">{}</text>"#,
+            x,
+            y + NODE_RADIUS + 12.0,
+            if n.ghost || n.context { "#888" } else { "#222" },
+            title
+        );
+        println!("    </g>");
+    }
+    println!("  </g>");
+    println!("</svg>");
+}
+
+/// Place `count` nodes on a plane, so that linked nodes are close to each other while all nodes
+/// push each other away (Fruchterman and Reingold's method), returning their coordinates
+///
+/// The nodes start out on a spiral, in order, so that the same graph is always laid out the same
+/// way. Every step looks at every pair of nodes, so this gets slow on graphs with many thousands
+/// of nodes.
+fn force_layout(count: usize, edges: &[(usize, usize)]) -> Vec<(f64, f64)>
+{
+    let k = LAYOUT_DISTANCE;
+    let golden_angle = std::f64::consts::PI * (3.0 - 5f64.sqrt());
+    let mut positions: Vec<(f64, f64)> = (0..count)
+        .map(|i| {
+            let (radius, angle) = (k * (i as f64 + 0.5).sqrt(), i as f64 * golden_angle);
+            (radius * angle.cos(), radius * angle.sin())
+        })
+        .collect();
+
+    let mut temperature = k * (count as f64).sqrt();
+    let cooling = temperature / LAYOUT_ITERATIONS as f64;
+    for _ in 0..LAYOUT_ITERATIONS {
+        // every node is pushed away from every other node by k²/d...
+        let mut moves: Vec<(f64, f64)> = (0..count)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = positions[i];
+                let mut total = (-x * LAYOUT_GRAVITY, -y * LAYOUT_GRAVITY);
+                for (j, &(other_x, other_y)) in positions.iter().enumerate() {
+                    if i == j {
+                        continue;
+                    }
+                    let (dx, dy) = (x - other_x, y - other_y);
+                    let force = k * k / (dx * dx + dy * dy).max(0.01);
+                    total.0 += dx * force;
+                    total.1 += dy * force;
+                }
+                total
+            })
+            .collect();
+        // ...and pulled towards the nodes it's linked with by d²/k
+        for &(u, v) in edges {
+            let (dx, dy) = (
+                positions[u].0 - positions[v].0,
+                positions[u].1 - positions[v].1,
+            );
+            let force = (dx * dx + dy * dy).sqrt() / k;
+            moves[u].0 -= dx * force;
+            moves[u].1 -= dy * force;
+            moves[v].0 += dx * force;
+            moves[v].1 += dy * force;
+        }
+        // no node moves further than the temperature, which cools down until everything settles
+        for (p, m) in positions.iter_mut().zip(&moves) {
+            let length = (m.0 * m.0 + m.1 * m.1).sqrt();
+            if length > 0.0 {
+                let step = length.min(temperature);
+                p.0 += m.0 / length * step;
+                p.1 += m.1 / length * step;
+            }
+        }
+        temperature -= cooling;
+    }
+
+    positions
+}
+
+/// Turn a title into a Mermaid ID, which may only contain letters, digits and underscores
+///
+/// Every other character (and the underscore itself) is replaced by `_` followed by the hexadecimal
+/// value of its bytes, so that different titles never share an ID. The prefix keeps IDs from
+/// clashing with keywords such as `end`.
+fn mermaid_id(prefix: &str, title: &str) -> String
+{
+    let mut id = format!("{}_", prefix);
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            id.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for b in c.encode_utf8(&mut bytes).bytes() {
+                id.push_str(&format!("_{:02X}", b));
+            }
+        }
+    }
+    id
+}
+
+/// Escape the characters that would end or otherwise break a quoted Mermaid label
+fn escape_mermaid(text: &str) -> String
+{
+    text.replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
 }
 
-/// Turn a graph into its dot format, printing it to stdout
-fn dot_output(g: Graph<&str, &str>)
+/// Escape the characters that would end or otherwise break a quoted DOT ID
+fn escape_dot(text: &str) -> String
 {
-    println!("{}", Dot::with_config(&g, &[Config::EdgeNoLabel]));
+    text.replace('\\', "\\\\").replace('"', "\\\"")
 }
 
-fn json_output(g: Graph<&str, &str>) -> String
+/// Escape the characters that have a special meaning in XML, both in text and in attributes
+fn escape_xml(text: &str) -> String
 {
-    serde_json::to_string(&g).unwrap()
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
 }
 
-pub fn vizk(zs: &[Zettel])
+pub fn vizk(zs: &[Zettel], opts: &GraphOptions, sizes: Option<&HashMap<String, f64>>)
 {
-    let jsongraph = json_output(gen_graph(zs));
+    let jsongraph = json_output(zs, opts);
+    let sizes = serde_json::to_string(&sizes).unwrap();
     println!(
         r#"<!DOCTYPE html>
 <html>
@@ -198,6 +1746,8 @@ pub fn vizk(zs: &[Zettel])
     <script src="https://cdn.jsdelivr.net/npm/d3@7"></script>
     <script type="module">
         const raw_json_input = {};
+        // maps titles to node sizes; if null, nodes are sized by their number of links
+        const custom_sizes = {};
 
         const width = window.innerWidth;
         const height = window.innerHeight;
@@ -239,23 +1789,21 @@ pub fn vizk(zs: &[Zettel])
         const linkOpacity = 0.6;
         const unhighlightedOpacity = 0.3;
         const ghostOpacity = 0.4;
+        const contextColor = "\#555";
+        const tagColor = "\#e69f00";
         const unhighlightedGhostColor = "\#373737";
 
         const renderRegular = 0;
         const renderHighlight = 1;
         const renderUnhighlight = 2;
 
+        // edges refer to nodes by their IDs, but the simulation refers to them by their indices
+        const nodeIndices = new Map(raw_json_input.nodes.map((n, i) => [n.id, i]));
         let graph = {{
-            nodes: raw_json_input.nodes.map((n) => {{return {{name: n, render: renderRegular, exists: true}}}}),
-            links: raw_json_input.edges.map((l) => {{return {{source: l[0], target: l[1], render: renderRegular}}}})
+            nodes: raw_json_input.nodes.map((n) => {{return {{name: n.title, render: renderRegular, exists: !n.ghost, context: n.context, tag: n.type == "tag"}}}}),
+            links: raw_json_input.edges.map((l) => {{return {{source: nodeIndices.get(l.source), target: nodeIndices.get(l.target), render: renderRegular}}}})
         }};
 
-        raw_json_input.edges.forEach((l) => {{
-            if (l[2] == "ghost") {{
-                graph.nodes[l[1]].exists = false;
-            }}
-        }});
-
         const canvas = d3.select("body").append("canvas")
             .attr("width", width)
             .attr("height", height);
@@ -326,7 +1874,7 @@ pub fn vizk(zs: &[Zettel])
             graph.nodes.forEach((d) => {{
                 let currentTextColor = textColor;
                 let currentTextOpacity = textOpacity;
-                let currentNodeColor = nodeColor;
+                let currentNodeColor = d.tag ? tagColor : d.context ? contextColor : nodeColor;
                 let currentNodeOpacity = nodeOpacity;
                 if (d.exists) {{
                     switch(d.render) {{
@@ -382,6 +1930,11 @@ pub fn vizk(zs: &[Zettel])
             graph.nodes[link.source]["size"]++;
             graph.nodes[link.target]["size"]++;
         }});
+        if (custom_sizes) {{
+            graph.nodes.forEach((d) => {{
+                d.size = custom_sizes[d.name] ?? 1;
+            }});
+        }}
 
         const linkedByIndex = {{}};
         graph.links.forEach(d => {{
@@ -575,6 +2128,448 @@ pub fn vizk(zs: &[Zettel])
     </script>
 </body>
 </html>"#,
-        jsongraph
+        jsongraph, sizes
     );
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::time::Instant;
+
+    /// Make a note that links to the given notes
+    fn zettel(title: &str, links: &[&str]) -> Zettel
+    {
+        let mut z = Zettel::new(title, "");
+        z.links = links.iter().map(|l| l.to_string()).collect();
+        z
+    }
+
+    #[test]
+    fn neighbourhood_is_bounded_by_depth_and_direction()
+    {
+        // A -> B -> C -> D, and E -> B
+        let mut zs = vec![
+            zettel("A", &["B"]),
+            zettel("B", &["C"]),
+            zettel("C", &["D", "Ghost"]),
+            zettel("D", &[]),
+            zettel("E", &["B"]),
+        ];
+        zs[1].backlinks = vec!["A".to_string(), "E".to_string()];
+        zs[2].backlinks = vec!["B".to_string()];
+        zs[3].backlinks = vec!["C".to_string()];
+        let start = [zs[1].clone()];
+        let sorted = |distances: HashMap<String, usize>| {
+            let mut distances: Vec<(String, usize)> = distances.into_iter().collect();
+            distances.sort();
+            distances
+        };
+        let d = |pairs: &[(&str, usize)]| -> Vec<(String, usize)> {
+            pairs.iter().map(|(t, n)| (t.to_string(), *n)).collect()
+        };
+
+        assert_eq!(
+            sorted(neighbourhood(&zs, &start, 2, Direction::Out)),
+            d(&[("B", 0), ("C", 1), ("D", 2)])
+        );
+        assert_eq!(
+            sorted(neighbourhood(&zs, &start, 1, Direction::In)),
+            d(&[("A", 1), ("B", 0), ("E", 1)])
+        );
+        assert_eq!(
+            sorted(neighbourhood(&zs, &start, 1, Direction::Both)),
+            d(&[("A", 1), ("B", 0), ("C", 1), ("E", 1)])
+        );
+        assert_eq!(
+            sorted(neighbourhood(&zs, &start, 0, Direction::Both)),
+            d(&[("B", 0)])
+        );
+    }
+
+    #[test]
+    fn shortest_paths_come_first()
+    {
+        let zs = vec![
+            zettel("A", &["B", "C", "D"]),
+            zettel("B", &["C", "D"]),
+            zettel("C", &["D"]),
+            zettel("D", &[]),
+        ];
+        let paths = shortest_paths(&zs, "A", "D", 10, false);
+        let lengths: Vec<usize> = paths.iter().map(|p| p.len()).collect();
+        assert_eq!(lengths, [2, 3, 3, 4]);
+        assert_eq!(paths[0], ["A", "D"]);
+        assert!(paths.contains(&vec!["A".into(), "B".into(), "D".into()]));
+        assert!(paths.contains(&vec!["A".into(), "C".into(), "D".into()]));
+        assert_eq!(paths[3], ["A", "B", "C", "D"]);
+
+        assert_eq!(shortest_paths(&zs, "A", "D", 2, false).len(), 2);
+    }
+
+    #[test]
+    fn shortest_paths_follow_backlinks_if_undirected()
+    {
+        let zs = vec![zettel("A", &["B"]), zettel("B", &["C"]), zettel("C", &[])];
+        assert!(shortest_paths(&zs, "C", "A", 1, false).is_empty());
+        assert_eq!(shortest_paths(&zs, "C", "A", 1, true), [["C", "B", "A"]]);
+        assert!(shortest_paths(&zs, "A", "Nowhere", 1, false).is_empty());
+    }
+
+    #[test]
+    fn related_notes_share_neighbours()
+    {
+        let mut zs = vec![
+            zettel("Index", &["Start", "Sibling", "Other"]),
+            zettel("Start", &["Topic", "Ghost"]),
+            zettel("Peer", &["Topic", "Ghost"]),
+            zettel("Sibling", &["Topic"]),
+            zettel("Other", &[]),
+            zettel("Topic", &[]),
+            zettel("Far", &["Other"]),
+        ];
+        zs[1].backlinks = vec!["Index".to_string()];
+        let start = [zs[1].clone()];
+
+        let mut scores: Vec<(String, f64)> = related(&zs, &start).into_iter().collect();
+        scores.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            scores,
+            [
+                // co-cited by `Index`
+                ("Other".to_string(), 1.0),
+                // links to the same notes, ghosts included
+                ("Peer".to_string(), 2.0),
+                // both
+                ("Sibling".to_string(), 2.0),
+            ]
+        );
+    }
+
+    /// Return the centrality of every note, indexed by title
+    fn centrality_by_title(zs: &[Zettel]) -> HashMap<String, Centrality>
+    {
+        centrality(zs, None)
+            .into_iter()
+            .map(|c| (c.title.clone(), c))
+            .collect()
+    }
+
+    #[test]
+    fn pagerank_favours_notes_with_backlinks()
+    {
+        let zs = vec![
+            zettel("C", &["Hub", "A"]),
+            zettel("A", &["Hub"]),
+            zettel("B", &["Hub"]),
+            zettel("Hub", &["Ghost"]),
+        ];
+        let c = centrality_by_title(&zs);
+        let total: f64 = c.values().map(|c| c.pagerank).sum();
+        assert!((total - 1.0).abs() < 1e-6, "{}", total);
+        assert!(c["Hub"].pagerank > c["A"].pagerank);
+        assert!(c["A"].pagerank > c["B"].pagerank);
+        assert_eq!(c["B"].pagerank, c["C"].pagerank);
+        assert!(c["Ghost"].ghost && !c["Hub"].ghost);
+        assert_eq!((c["Hub"].in_degree, c["Hub"].out_degree), (3, 1));
+    }
+
+    #[test]
+    fn betweenness_counts_the_paths_going_through()
+    {
+        // every path from `Left` goes through `Middle`, and half of the paths to `Right` go through
+        // `Top`
+        let zs = vec![
+            zettel("Left", &["Middle"]),
+            zettel("Middle", &["Top", "Bottom"]),
+            zettel("Top", &["Right"]),
+            zettel("Bottom", &["Right"]),
+            zettel("Right", &[]),
+        ];
+        let c = centrality_by_title(&zs);
+        // out of the 4 * 3 ordered pairs of other notes
+        let pairs = 12.0;
+        assert!((c["Middle"].betweenness - 3.0 / pairs).abs() < 1e-9);
+        assert!((c["Top"].betweenness - 1.0 / pairs).abs() < 1e-9);
+        assert_eq!(c["Left"].betweenness, 0.0);
+        assert_eq!(c["Right"].betweenness, 0.0);
+    }
+
+    #[test]
+    fn filtered_out_notes_are_not_central_ghosts()
+    {
+        // `B` exists, but didn't make it into the results, unlike `A`
+        let zs = vec![zettel("A", &["B", "Ghost"])];
+        let existing: HashSet<String> = ["A", "B"].iter().map(|t| t.to_string()).collect();
+        let ghosts = |c: Vec<Centrality>| -> Vec<String> {
+            let mut ghosts: Vec<String> =
+                c.into_iter().filter(|c| c.ghost).map(|c| c.title).collect();
+            ghosts.sort();
+            ghosts
+        };
+
+        assert_eq!(ghosts(centrality(&zs, Some(&existing))), ["Ghost"]);
+        assert_eq!(ghosts(centrality(&zs, None)), ["B", "Ghost"]);
+    }
+
+    /// Two triangles of notes, joined by a single link, plus a note on its own
+    fn two_triangles() -> Vec<Zettel>
+    {
+        vec![
+            zettel("A", &["B", "C"]),
+            zettel("B", &["C"]),
+            zettel("C", &["X"]),
+            zettel("X", &["Y", "Z"]),
+            zettel("Y", &["Z"]),
+            zettel("Z", &[]),
+            zettel("Alone", &[]),
+        ]
+    }
+
+    #[test]
+    fn louvain_splits_loosely_linked_groups()
+    {
+        let clusters = clusters(&two_triangles(), None, Clustering::Louvain);
+        let members: Vec<&[String]> = clusters.iter().map(|c| c.members.as_slice()).collect();
+        assert_eq!(
+            members,
+            [
+                &["A".to_string(), "B".to_string(), "C".to_string()][..],
+                &["X".to_string(), "Y".to_string(), "Z".to_string()][..],
+                &["Alone".to_string()][..],
+            ]
+        );
+        // the notes that join the two groups are the most central ones
+        assert_eq!(clusters[0].central, "C");
+        assert_eq!(clusters[1].central, "Z");
+    }
+
+    #[test]
+    fn components_follow_links_in_any_direction()
+    {
+        let clusters = clusters(&two_triangles(), None, Clustering::Components);
+        let sizes: Vec<usize> = clusters.iter().map(|c| c.members.len()).collect();
+        assert_eq!(sizes, [6, 1]);
+    }
+
+    #[test]
+    fn cycles_are_bounded_by_their_length()
+    {
+        let zs = vec![
+            zettel("E", &["C"]),
+            zettel("B", &["A", "C"]),
+            zettel("A", &["B"]),
+            zettel("C", &["D"]),
+            zettel("D", &["B", "Ghost"]),
+        ];
+        let strings = |cycles: Vec<Vec<String>>| -> Vec<String> {
+            cycles.iter().map(|c| c.join(" -> ")).collect()
+        };
+
+        assert_eq!(strings(cycles(&zs, None, 2)), ["A -> B"]);
+        assert_eq!(strings(cycles(&zs, None, 3)), ["A -> B", "B -> C -> D"]);
+        assert!(cycles(&zs, None, 1).is_empty());
+    }
+
+    #[test]
+    fn cycles_are_only_found_once()
+    {
+        // the same cycle may be walked into from any of its notes
+        let zs = vec![
+            zettel("Start", &["A"]),
+            zettel("A", &["B", "C"]),
+            zettel("B", &["C", "A"]),
+            zettel("C", &["A", "B"]),
+        ];
+        let cycles: Vec<String> = cycles(&zs, None, 5)
+            .iter()
+            .map(|c| c.join(" -> "))
+            .collect();
+        assert_eq!(
+            cycles,
+            ["A -> B", "A -> C", "B -> C", "A -> B -> C", "A -> C -> B"]
+        );
+    }
+
+    /// Make up a Zettelkasten of `notes` notes, each linking to `links` others picked at random,
+    /// about one in twenty of which don't exist
+    fn synthetic(notes: usize, links: usize) -> Vec<Zettel>
+    {
+        // a linear congruential generator is random enough, and keeps runs comparable
+        let mut state: u64 = 42;
+        let mut random = move || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize
+        };
+        (0..notes)
+            .map(|i| {
+                let mut z = Zettel::new(&format!("Note {}", i), "");
+                z.links = (0..links)
+                    .map(|_| format!("Note {}", random() % (notes + notes / 20)))
+                    .collect();
+                z
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filtered_out_notes_are_not_ghosts()
+    {
+        // `B` exists, but didn't make it into the results, unlike `A`
+        let zs = vec![zettel("A", &["B", "Ghost"])];
+        let opts = GraphOptions {
+            existing: Some(["A", "B"].iter().map(|t| t.to_string()).collect()),
+            ..Default::default()
+        };
+        let (nodes, edges) = attributed(&zs, &opts);
+
+        let ghosts: Vec<&str> = nodes
+            .iter()
+            .filter(|n| n.ghost)
+            .map(|n| n.id.as_ref())
+            .collect();
+        assert_eq!(ghosts, ["Ghost"]);
+        let ghost_edges: Vec<&str> = edges
+            .iter()
+            .filter(|e| e.ghost)
+            .map(|e| e.id.as_str())
+            .collect();
+        assert_eq!(ghost_edges, ["A -> Ghost"]);
+
+        // without knowing the rest of the Zettelkasten, anything outside the results is a ghost
+        let (nodes, _) = attributed(&zs, &GraphOptions::default());
+        assert_eq!(nodes.iter().filter(|n| n.ghost).count(), 2);
+    }
+
+    #[test]
+    fn dot_only_dashes_ghosts()
+    {
+        let zs = vec![zettel("A", &["B", "Ghost"])];
+        let opts = GraphOptions {
+            existing: Some(["A", "B"].iter().map(|t| t.to_string()).collect()),
+            ..Default::default()
+        };
+        let dot = dot_output(&zs, &opts);
+
+        assert!(dot.contains("    \"B\";\n"), "{}", dot);
+        assert!(dot.contains("    \"A\" -> \"B\";\n"), "{}", dot);
+        assert!(
+            dot.contains("    \"Ghost\" [style = \"dashed\"];\n"),
+            "{}",
+            dot
+        );
+        assert!(
+            dot.contains("    \"A\" -> \"Ghost\" [style = dashed];\n"),
+            "{}",
+            dot
+        );
+    }
+
+    #[test]
+    fn expanded_subgraph_keeps_links_to_ghosts()
+    {
+        let mut all = vec![
+            zettel("A", &["B", "Ghost"]),
+            zettel("B", &["C", "Other ghost"]),
+            zettel("C", &[]),
+        ];
+        all[1].backlinks = vec!["A".to_string()];
+        all[2].backlinks = vec!["B".to_string()];
+        let results = vec![all[0].clone()];
+
+        let (zs, context) = subgraph(&all, &results, Subgraph::Expanded(1));
+        let links: Vec<(&str, &[String])> = zs
+            .iter()
+            .map(|z| (z.title.as_str(), z.links.as_slice()))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                ("A", &["B".to_string(), "Ghost".to_string()][..]),
+                ("B", &["Other ghost".to_string()][..]),
+            ]
+        );
+        assert_eq!(context, HashSet::from(["B".to_string()]));
+
+        // whereas the induced subgraph only keeps the links between the results
+        let (zs, _) = subgraph(&all, &results, Subgraph::Induced);
+        assert!(zs[0].links.is_empty());
+    }
+
+    #[test]
+    fn tags_dont_clash_with_notes()
+    {
+        let mut a = zettel("A", &["#idea"]);
+        a.tags = vec!["idea".to_string()];
+        let zs = vec![a, zettel("#idea", &[])];
+        let opts = GraphOptions {
+            tags: true,
+            ..Default::default()
+        };
+        let (nodes, edges) = attributed(&zs, &opts);
+
+        let nodes: Vec<(&str, &str, &str)> = nodes
+            .iter()
+            .map(|n| (n.id.as_ref(), n.title.as_ref(), n.kind))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                ("note:#idea", "#idea", "note"),
+                ("note:A", "A", "note"),
+                ("tag:idea", "#idea", "tag"),
+            ]
+        );
+        let edges: Vec<(&str, &str)> = edges.iter().map(|e| (e.id.as_str(), e.kind)).collect();
+        assert_eq!(
+            edges,
+            [
+                ("note:A -> note:#idea", "link"),
+                ("note:A -> tag:idea", "tag"),
+            ]
+        );
+    }
+
+    #[test]
+    fn gen_graph_adds_every_note_once()
+    {
+        let mut a = Zettel::new("A", "");
+        a.links = vec!["B".to_string(), "Ghost".to_string()];
+        let mut b = Zettel::new("B", "");
+        b.links = vec!["A".to_string()];
+
+        let zs = vec![a, b];
+        let g = gen_graph(&zs, None);
+        assert_eq!(g.node_count(), 3);
+        assert_eq!(g.edge_count(), 3);
+        let ghost = find_node(&g, "Ghost").unwrap();
+        assert!(g
+            .edges_directed(ghost, Incoming)
+            .all(|e| *e.weight() == "ghost"));
+        let a = find_node(&g, "A").unwrap();
+        assert_eq!(a.index(), 0);
+        assert_eq!(g.edges_directed(a, Incoming).count(), 1);
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_gen_graph()
+    {
+        let zs = synthetic(100_000, 10);
+        let start = Instant::now();
+        let g = gen_graph(&zs, None);
+        let elapsed = start.elapsed();
+        println!(
+            "gen_graph: {} nodes, {} edges in {:?}",
+            g.node_count(),
+            g.edge_count(),
+            elapsed
+        );
+        assert_eq!(g.edge_count(), 1_000_000);
+        assert!(g.node_count() >= 100_000);
+    }
+}
@@ -1,6 +1,7 @@
 use glob::glob;
 use std::fs::{canonicalize, read_to_string, write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 /// Return true if the path specified exists and is a file
 pub fn file_exists(path: &str) -> bool
@@ -14,6 +15,25 @@ pub fn dir_exists(path: &str) -> bool
     Path::new(path).is_dir()
 }
 
+/// Return the times at which the file was created and last modified, as UNIX timestamps, or zero
+/// if they couldn't be read. If the filesystem doesn't keep track of creation times, then the
+/// modification time is used in its stead.
+pub fn file_times(path: &str) -> (i64, i64)
+{
+    let timestamp = |t: SystemTime| {
+        t.duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64)
+    };
+    match std::fs::metadata(path) {
+        Ok(m) => {
+            let modified = m.modified().map_or(0, timestamp);
+            let created = m.created().map_or(modified, timestamp);
+            (created, modified)
+        }
+        Err(_) => (0, 0),
+    }
+}
+
 /// Return the last segment of a path
 pub fn basename(path: &str) -> String
 {
@@ -3,6 +3,8 @@ mod config;
 mod database;
 mod graph;
 mod io;
+mod printer;
+mod search;
 mod subcommands;
 mod zettel;
 
@@ -11,7 +13,9 @@ use crate::database::Database;
 use crate::subcommands::*;
 use crate::zettel::Zettel;
 
-fn main() -> Result<(), rusqlite::Error>
+use std::process::ExitCode;
+
+fn main() -> Result<ExitCode, rusqlite::Error>
 {
     let matches = cli::build().get_matches();
 
@@ -23,10 +27,21 @@ fn main() -> Result<(), rusqlite::Error>
     match cmd {
         "sync" => sync(cmd_matches, &ConfigOptions::load())?,
         "query" => query(cmd_matches, &ConfigOptions::load())?,
+        "pick" => {
+            if let Err(e) = pick(cmd_matches, &ConfigOptions::load()) {
+                eprintln!("error: {}", e);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        "suggest" => suggest(cmd_matches, &ConfigOptions::load())?,
+        "duplicates" => duplicates(cmd_matches, &ConfigOptions::load())?,
+        "centrality" => centrality(cmd_matches, &ConfigOptions::load())?,
+        "clusters" => clusters(cmd_matches, &ConfigOptions::load())?,
+        "cycles" => cycles(cmd_matches, &ConfigOptions::load())?,
         "ls" => ls(cmd_matches, &ConfigOptions::load())?,
         "compl" => compl(cmd_matches)?,
         _ => (),
     };
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
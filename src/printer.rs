@@ -0,0 +1,647 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::config::ConfigOptions;
+use crate::Zettel;
+
+/// A printer that prints. Because it's convenient.
+pub struct Printer
+{
+    zettel: Vec<Zettel>,
+    // Additional data about some of the Zettel, indexed by their titles
+    additional: HashMap<String, String>,
+    distances: HashMap<String, usize>,
+    scores: HashMap<String, f64>,
+    // Print according to a certain format, replacing the following placeholder tokens
+    //
+    //  %t - title
+    //  %p - project
+    //  %P - path
+    //  %l - (forward) links
+    //  %b - backlinks
+    //  %g - tags
+    //  %a - contents of the `additional` field (--text flag fills this with the matched pattern)
+    //  %d - contents of the `distances` field (--near flag fills this with the number of hops)
+    //  %s - contents of the `scores` field (--search and --related flags fill this with the relevance)
+    //
+    // Placeholders may also be written as `%[-][WIDTH][#]X`, where `-` aligns to the left, WIDTH
+    // pads to that many characters, and `#` prints the number of links, backlinks or tags instead.
+    // Everything between `%(` and `%)` is only printed if none of its placeholders are empty.
+    format: String,
+    link_separator: String,
+    header: String,
+    footer: String,
+    sort_by: SortKey,
+    reverse: bool,
+    limit: Option<usize>,
+    output: Output,
+}
+
+impl Printer
+{
+    pub fn set_zettelkasten(&mut self, new_zettel: Vec<Zettel>)
+    {
+        self.zettel = new_zettel;
+    }
+
+    pub fn set_additional(&mut self, new_additional: HashMap<String, String>)
+    {
+        self.additional = new_additional;
+    }
+
+    pub fn set_distances(&mut self, new_distances: HashMap<String, usize>)
+    {
+        self.distances = new_distances;
+    }
+
+    pub fn set_scores(&mut self, new_scores: HashMap<String, f64>)
+    {
+        self.scores = new_scores;
+    }
+
+    pub fn set_format(&mut self, new_format: String)
+    {
+        self.format = new_format;
+    }
+
+    pub fn set_link_separator(&mut self, new_separator: String)
+    {
+        self.link_separator = new_separator;
+    }
+
+    pub fn set_header(&mut self, new_header: String)
+    {
+        self.header = new_header;
+    }
+
+    pub fn set_footer(&mut self, new_footer: String)
+    {
+        self.footer = new_footer;
+    }
+
+    pub fn set_order(&mut self, sort_by: SortKey, reverse: bool, limit: Option<usize>)
+    {
+        self.sort_by = sort_by;
+        self.reverse = reverse;
+        self.limit = limit;
+    }
+
+    pub fn set_output(&mut self, new_output: Output)
+    {
+        self.output = new_output;
+    }
+
+    pub fn print_one(&mut self, cfg: &ConfigOptions, zettel: Zettel)
+    {
+        self.zettel = vec![zettel];
+        self.print(cfg);
+    }
+
+    /// Abracadabra, yadda yadda. Print everything properly.
+    pub fn print(&mut self, cfg: &ConfigOptions)
+    {
+        self.order();
+
+        match self.output {
+            Output::Format => (),
+            Output::Json => {
+                let records: Vec<Record> =
+                    self.zettel.iter().map(|z| self.record(cfg, z)).collect();
+                println!("{}", serde_json::to_string(&records).unwrap());
+                return;
+            }
+            Output::Ndjson => {
+                for z in &self.zettel {
+                    println!("{}", serde_json::to_string(&self.record(cfg, z)).unwrap());
+                }
+                return;
+            }
+            Output::Csv | Output::Tsv => {
+                self.print_table(cfg);
+                return;
+            }
+        }
+
+        if !self.header.is_empty() {
+            println!("{}", self.header);
+        }
+
+        let tokens = parse_format(&self.format);
+        for z in &self.zettel {
+            let (result, _) = self.render(cfg, z, &tokens);
+            println!("{}", result);
+        }
+
+        if !self.footer.is_empty() {
+            println!("{}", self.footer);
+        }
+    }
+
+    /// Sort the Zettel, as `order` does, and return them
+    pub fn ordered(&mut self) -> &[Zettel]
+    {
+        self.order();
+        &self.zettel
+    }
+}
+
+impl Printer
+{
+    /// Sort the Zettel by the chosen key, maybe in reverse, breaking ties by title (which are
+    /// always in alphabetical order), then keep only the first few of them
+    fn order(&mut self)
+    {
+        let scores = &self.scores;
+        let score = |z: &Zettel| scores.get(&z.title).copied().unwrap_or_default();
+        let (sort_by, reverse) = (self.sort_by, self.reverse);
+        let key = |a: &Zettel, b: &Zettel| match sort_by {
+            SortKey::Title => a.cmp(b),
+            SortKey::Project => a.project.cmp(&b.project),
+            SortKey::Links => a.links.len().cmp(&b.links.len()),
+            SortKey::Backlinks => a.backlinks.len().cmp(&b.backlinks.len()),
+            SortKey::Tags => a.tags.len().cmp(&b.tags.len()),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+            SortKey::Score => score(a).total_cmp(&score(b)),
+        };
+        self.zettel.sort_by(|a, b| {
+            let order = key(a, b);
+            let order = if reverse { order.reverse() } else { order };
+            order.then_with(|| a.cmp(b))
+        });
+        if let Some(limit) = self.limit {
+            self.zettel.truncate(limit);
+        }
+    }
+
+    /// Replace the placeholders in the tokens with the data of the given Zettel. Also return
+    /// whether all of the placeholders had a (non-empty) value.
+    fn render(&self, cfg: &ConfigOptions, z: &Zettel, tokens: &[Token]) -> (String, bool)
+    {
+        let mut result = String::new();
+        let mut complete = true;
+        for token in tokens {
+            match token {
+                Token::Text(text) => result.push_str(text),
+                Token::Placeholder {
+                    flag,
+                    count,
+                    width,
+                    left_align,
+                } => {
+                    let value = self.value(cfg, z, *flag, *count);
+                    complete &= !value.is_empty();
+                    if *left_align {
+                        result.push_str(&format!("{:<1$}", value, width));
+                    } else {
+                        result.push_str(&format!("{:>1$}", value, width));
+                    }
+                }
+                Token::Optional(inner) => {
+                    let (section, section_complete) = self.render(cfg, z, inner);
+                    if section_complete {
+                        result.push_str(&section);
+                    }
+                }
+            }
+        }
+        (result, complete)
+    }
+
+    /// Return the value of a placeholder flag, for the given Zettel
+    fn value(&self, cfg: &ConfigOptions, z: &Zettel, flag: char, count: bool) -> String
+    {
+        let list = match flag {
+            'l' => &z.links,
+            'b' => &z.backlinks,
+            'g' => &z.tags,
+            _ => &z.links,
+        };
+        match flag {
+            'l' | 'b' | 'g' if count => list.len().to_string(),
+            'l' | 'b' | 'g' => list.join(&self.link_separator),
+            't' => z.title.clone(),
+            'p' => z.project.clone(),
+            'P' => z.filename(cfg),
+            'a' => self.additional.get(&z.title).cloned().unwrap_or_default(),
+            'd' => self
+                .distances
+                .get(&z.title)
+                .map_or("".to_string(), |d| d.to_string()),
+            's' => self
+                .scores
+                .get(&z.title)
+                .map_or("".to_string(), |s| format!("{:.2}", s)),
+            _ => String::new(),
+        }
+    }
+
+    /// Gather all the data about a Zettel in a single structure
+    fn record<'a>(&'a self, cfg: &ConfigOptions, z: &'a Zettel) -> Record<'a>
+    {
+        Record {
+            title: &z.title,
+            project: &z.project,
+            path: z.filename(cfg),
+            tags: &z.tags,
+            links: &z.links,
+            backlinks: &z.backlinks,
+            text_match: self.additional.get(&z.title).map_or("", |a| a.as_str()),
+        }
+    }
+
+    /// Print the Zettel as a table of comma- or tab-separated values, preceded by a header
+    fn print_table(&self, cfg: &ConfigOptions)
+    {
+        let (separator, escape): (&str, fn(&str) -> String) = match self.output {
+            Output::Tsv => ("\t", escape_tsv),
+            _ => (",", escape_csv),
+        };
+        println!(
+            "{}",
+            [
+                "title",
+                "project",
+                "path",
+                "tags",
+                "links",
+                "backlinks",
+                "match"
+            ]
+            .join(separator)
+        );
+        for z in &self.zettel {
+            let r = self.record(cfg, z);
+            let fields = [
+                r.title.to_string(),
+                r.project.to_string(),
+                r.path,
+                r.tags.join(&self.link_separator),
+                r.links.join(&self.link_separator),
+                r.backlinks.join(&self.link_separator),
+                r.text_match.to_string(),
+            ];
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            println!("{}", fields.join(separator));
+        }
+    }
+}
+
+impl Default for Printer
+{
+    fn default() -> Printer
+    {
+        Printer {
+            zettel: vec![],
+            additional: HashMap::new(),
+            distances: HashMap::new(),
+            scores: HashMap::new(),
+            format: "[%p] %t".to_string(),
+            link_separator: "|".to_string(),
+            header: String::new(),
+            footer: String::new(),
+            sort_by: SortKey::Title,
+            reverse: false,
+            limit: None,
+            output: Output::Format,
+        }
+    }
+}
+
+/// The property by which Zettel are sorted
+#[derive(Clone, Copy)]
+pub enum SortKey
+{
+    Title,
+    Project,
+    Links,
+    Backlinks,
+    Tags,
+    Modified,
+    Score,
+}
+
+/// The way in which the Printer prints
+#[derive(Clone, Copy)]
+pub enum Output
+{
+    /// According to the format string
+    Format,
+    /// As a single JSON array of objects
+    Json,
+    /// As a JSON object per line
+    Ndjson,
+    /// As comma-separated values
+    Csv,
+    /// As tab-separated values
+    Tsv,
+}
+
+/// All the data about a Zettel, as printed by the structured output formats
+#[derive(Serialize)]
+struct Record<'a>
+{
+    title: &'a str,
+    project: &'a str,
+    path: String,
+    tags: &'a [String],
+    links: &'a [String],
+    backlinks: &'a [String],
+    #[serde(rename = "match")]
+    text_match: &'a str,
+}
+
+/// Quote a CSV field if it contains special characters, as per RFC 4180
+pub fn escape_csv(field: &str) -> String
+{
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escape the characters that would otherwise break a TSV field
+fn escape_tsv(field: &str) -> String
+{
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// The flags that may be used in placeholders
+const FLAGS: &str = "tpPlbgads";
+
+/// A piece of a format string
+#[derive(Debug, PartialEq)]
+enum Token
+{
+    /// Printed as is
+    Text(String),
+    /// Replaced with some data about the Zettel
+    Placeholder
+    {
+        flag: char,
+        count: bool,
+        width: usize,
+        left_align: bool,
+    },
+    /// Printed only if none of its placeholders are empty
+    Optional(Vec<Token>),
+}
+
+/// Split a format string into tokens
+fn parse_format(format: &str) -> Vec<Token>
+{
+    parse_tokens(&mut format.chars().peekable(), false)
+}
+
+/// Turn characters into tokens until the end of the string or, if inside of an optional section,
+/// until the end of said section
+fn parse_tokens(chars: &mut Peekable<Chars>, in_section: bool) -> Vec<Token>
+{
+    let mut tokens = vec![];
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('%') => {
+                chars.next();
+                text.push('%');
+                continue;
+            }
+            Some(')') if in_section => {
+                chars.next();
+                break;
+            }
+            _ => (),
+        }
+
+        tokens.push(Token::Text(std::mem::take(&mut text)));
+        if chars.peek() == Some(&'(') {
+            chars.next();
+            tokens.push(Token::Optional(parse_tokens(chars, true)));
+            continue;
+        }
+
+        // `raw` is printed as is if this turns out not to be a placeholder after all
+        let mut raw = String::from("%");
+        let left_align = chars.next_if_eq(&'-').is_some();
+        if left_align {
+            raw.push('-');
+        }
+        let mut digits = String::new();
+        while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+            digits.push(d);
+        }
+        raw.push_str(&digits);
+        let count = chars.next_if_eq(&'#').is_some();
+        if count {
+            raw.push('#');
+        }
+        match chars.next_if(|f| FLAGS.contains(*f)) {
+            Some(flag) => tokens.push(Token::Placeholder {
+                flag,
+                count,
+                width: digits.parse().unwrap_or(0),
+                left_align,
+            }),
+            None => text.push_str(&raw),
+        }
+    }
+    tokens.push(Token::Text(text));
+    tokens
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Sort Zettel with the given number of links, named after their position
+    fn ordered_titles(links: &[usize], sort_by: SortKey, reverse: bool) -> Vec<String>
+    {
+        let zs = links
+            .iter()
+            .zip('A'..)
+            .map(|(n, title)| {
+                let mut z = Zettel::new(&title.to_string(), "");
+                z.links = vec![String::new(); *n];
+                z
+            })
+            .collect();
+        let mut printer = Printer::default();
+        printer.set_zettelkasten(zs);
+        printer.set_order(sort_by, reverse, None);
+        printer.ordered().iter().map(|z| z.title.clone()).collect()
+    }
+
+    #[test]
+    fn ties_are_broken_alphabetically()
+    {
+        assert_eq!(
+            ordered_titles(&[2, 1, 2], SortKey::Links, false),
+            ["B", "A", "C"]
+        );
+        assert_eq!(
+            ordered_titles(&[2, 1, 2], SortKey::Links, true),
+            ["A", "C", "B"]
+        );
+    }
+
+    #[test]
+    fn scores_are_tied_alphabetically_too()
+    {
+        let mut printer = Printer::default();
+        printer.set_zettelkasten(["C", "A", "B"].iter().map(|t| Zettel::new(t, "")).collect());
+        printer.set_scores(HashMap::from([
+            ("A".to_string(), 1.0),
+            ("B".to_string(), 2.0),
+            ("C".to_string(), 2.0),
+        ]));
+        printer.set_order(SortKey::Score, true, Some(2));
+        let titles: Vec<&str> = printer.ordered().iter().map(|z| z.title.as_str()).collect();
+        assert_eq!(titles, ["B", "C"]);
+    }
+
+    #[test]
+    fn titles_are_reversed()
+    {
+        assert_eq!(
+            ordered_titles(&[0, 0, 0], SortKey::Title, true),
+            ["C", "B", "A"]
+        );
+    }
+
+    #[test]
+    fn records_hold_all_the_data_about_a_note()
+    {
+        let cfg = ConfigOptions {
+            zettelkasten: "/zk".to_string(),
+            ..Default::default()
+        };
+        let mut z = Zettel::new("A", "inbox");
+        z.tags = vec!["t".to_string()];
+        z.links = vec!["B".to_string(), "C".to_string()];
+        let mut printer = Printer::default();
+        printer
+            .additional
+            .insert("A".to_string(), "1: line".to_string());
+
+        assert_eq!(
+            serde_json::to_string(&printer.record(&cfg, &z)).unwrap(),
+            r#"{"title":"A","project":"inbox","path":"/zk/inbox/A.md","tags":["t"],"links":["B","C"],"backlinks":[],"match":"1: line"}"#
+        );
+    }
+
+    #[test]
+    fn table_fields_are_escaped()
+    {
+        assert_eq!(escape_csv("plain"), "plain");
+        assert_eq!(escape_csv("a,b"), "\"a,b\"");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv("two\nlines"), "\"two\nlines\"");
+
+        assert_eq!(escape_tsv("plain, still"), "plain, still");
+        assert_eq!(escape_tsv("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    fn text(s: &str) -> Token
+    {
+        Token::Text(s.to_string())
+    }
+
+    fn placeholder(flag: char, count: bool, width: usize, left_align: bool) -> Token
+    {
+        Token::Placeholder {
+            flag,
+            count,
+            width,
+            left_align,
+        }
+    }
+
+    #[test]
+    fn parse_placeholders()
+    {
+        assert_eq!(
+            parse_format("[%p] %t"),
+            [
+                text("["),
+                placeholder('p', false, 0, false),
+                text("] "),
+                placeholder('t', false, 0, false),
+                text(""),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_widths_and_counts()
+    {
+        assert_eq!(
+            parse_format("%-20t|%5#b|%#l"),
+            [
+                text(""),
+                placeholder('t', false, 20, true),
+                text("|"),
+                placeholder('b', true, 5, false),
+                text("|"),
+                placeholder('l', true, 0, false),
+                text(""),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_escapes_and_unknown_flags()
+    {
+        assert_eq!(
+            parse_format("100%% %q %-5"),
+            [text("100% "), text("%q "), text("%-5")]
+        );
+        // outside of optional sections, `%)` is just text
+        assert_eq!(parse_format("a %) b"), [text("a "), text("%) b")]);
+    }
+
+    #[test]
+    fn parse_optional_sections()
+    {
+        assert_eq!(
+            parse_format("%t%( (%l)%)!"),
+            [
+                text(""),
+                placeholder('t', false, 0, false),
+                text(""),
+                Token::Optional(vec![
+                    text(" ("),
+                    placeholder('l', false, 0, false),
+                    text(")"),
+                ]),
+                text("!"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_unterminated_sections()
+    {
+        // the section runs until the end of the format
+        assert_eq!(
+            parse_format("%t%( %a"),
+            [
+                text(""),
+                placeholder('t', false, 0, false),
+                text(""),
+                Token::Optional(vec![text(" "), placeholder('a', false, 0, false), text(""),]),
+                text(""),
+            ]
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+
+use crate::config::ConfigOptions;
+use crate::io::file_to_string;
+use crate::zettel::strip_multiple_whitespace;
+use crate::Zettel;
+
+/// BM25 parameter that controls how quickly the score saturates as a term appears more often
+const K1: f64 = 1.2;
+/// BM25 parameter that controls how much longer notes are penalised
+const B: f64 = 0.75;
+/// How many times more a term in the title weighs than a term in the body
+const TITLE_BOOST: f64 = 3.0;
+/// The number of characters of context that are kept on each side of the first match in snippets
+const SNIPPET_CONTEXT: usize = 60;
+/// The number of consecutive words that make up a shingle
+const SHINGLE_SIZE: usize = 3;
+/// The number of hash functions used for MinHash signatures
+const MINHASH_SIZE: usize = 128;
+/// The least probability with which `minhash_candidates` finds a pair of signatures that are
+/// exactly as similar as the threshold
+const CANDIDATE_RECALL: f64 = 0.999;
+
+/// A search result: a Zettel, how relevant it is to the query, and an excerpt of its contents
+pub struct Hit
+{
+    pub zettel: Zettel,
+    pub score: f64,
+    pub snippet: String,
+}
+
+/// Split text into lowercase words
+pub fn tokenize(text: &str) -> Vec<String>
+{
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Rank the Zettel by their relevance to the query, using the BM25 scoring function over their
+/// titles and contents, and return those that are relevant at all, along with a snippet of text
+/// around the first match
+///
+/// Terms found in titles count `TITLE_BOOST` times as much as terms found in contents. The matches
+/// in snippets are only highlighted if `highlight` is set.
+pub fn ranked_search(cfg: &ConfigOptions, zs: &[Zettel], query: &str, highlight: bool) -> Vec<Hit>
+{
+    let contents: Vec<String> = zs
+        .par_iter()
+        .map(|z| file_to_string(&z.filename(cfg)))
+        .collect();
+    rank(zs, &contents, query, highlight)
+}
+
+/// Rank the Zettel, whose contents are given in the same order, as `ranked_search` does
+fn rank(zs: &[Zettel], contents: &[String], query: &str, highlight: bool) -> Vec<Hit>
+{
+    let mut terms = tokenize(query);
+    terms.sort();
+    terms.dedup();
+    if terms.is_empty() {
+        return vec![];
+    }
+
+    // weighted term frequencies and lengths of every document
+    let docs: Vec<(HashMap<String, f64>, f64)> = zs
+        .par_iter()
+        .zip(contents)
+        .map(|(z, c)| {
+            let mut freqs: HashMap<String, f64> = HashMap::new();
+            let title = tokenize(&z.title);
+            let body = tokenize(c);
+            for t in &title {
+                *freqs.entry(t.clone()).or_default() += TITLE_BOOST;
+            }
+            for t in &body {
+                *freqs.entry(t.clone()).or_default() += 1.0;
+            }
+            let len = TITLE_BOOST * title.len() as f64 + body.len() as f64;
+            (freqs, len)
+        })
+        .collect();
+
+    let n = docs.len() as f64;
+    let avg_len = (docs.iter().map(|(_, len)| len).sum::<f64>() / n).max(1.0);
+    let idfs: Vec<f64> = terms
+        .iter()
+        .map(|t| {
+            let containing = docs.iter().filter(|(f, _)| f.contains_key(t)).count() as f64;
+            (1.0 + (n - containing + 0.5) / (containing + 0.5)).ln()
+        })
+        .collect();
+
+    let highlighter = Highlighter::new(&terms, highlight);
+    zs.iter()
+        .zip(contents)
+        .zip(&docs)
+        .filter_map(|((z, c), (freqs, len))| {
+            let score: f64 = terms
+                .iter()
+                .zip(&idfs)
+                .map(|(t, idf)| {
+                    let tf = freqs.get(t).copied().unwrap_or_default();
+                    idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * len / avg_len))
+                })
+                .sum();
+            if score > 0.0 {
+                Some(Hit {
+                    zettel: z.clone(),
+                    score,
+                    snippet: highlighter.snippet(c),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Compares the contents of Zettel through the cosine similarity of their TF-IDF vectors
+pub struct Similarity
+{
+    vectors: Vec<HashMap<String, f64>>,
+    // for every term, the Zettel that contain it along with its weight in their vectors
+    postings: HashMap<String, Vec<(usize, f64)>>,
+}
+
+impl Similarity
+{
+    /// Build the TF-IDF vectors of the contents of the given Zettel, normalised to unit length
+    ///
+    /// Term frequencies are dampened logarithmically, so that a word that is repeated over and over
+    /// doesn't drown out the rest.
+    pub fn new(cfg: &ConfigOptions, zs: &[Zettel]) -> Self
+    {
+        let contents: Vec<String> = zs
+            .par_iter()
+            .map(|z| file_to_string(&z.filename(cfg)))
+            .collect();
+        Self::from_contents(&contents)
+    }
+
+    /// Build the TF-IDF vectors of the given texts, as `new` does
+    fn from_contents(contents: &[String]) -> Self
+    {
+        let counts: Vec<HashMap<String, f64>> = contents
+            .par_iter()
+            .map(|c| {
+                let mut counts: HashMap<String, f64> = HashMap::new();
+                for t in tokenize(c) {
+                    *counts.entry(t).or_default() += 1.0;
+                }
+                counts
+            })
+            .collect();
+
+        let mut containing: HashMap<&str, f64> = HashMap::new();
+        for c in &counts {
+            for t in c.keys() {
+                *containing.entry(t).or_default() += 1.0;
+            }
+        }
+        let n = counts.len() as f64;
+        let idfs: HashMap<&str, f64> = containing
+            .into_iter()
+            .map(|(t, df)| (t, (n / df).ln()))
+            .collect();
+
+        let vectors: Vec<HashMap<String, f64>> = counts
+            .iter()
+            .map(|c| {
+                let mut v: HashMap<String, f64> = c
+                    .iter()
+                    .map(|(t, tf)| (t.clone(), (1.0 + tf.ln()) * idfs[t.as_str()]))
+                    .filter(|(_, w)| *w > 0.0)
+                    .collect();
+                let norm = v.values().map(|w| w * w).sum::<f64>().sqrt();
+                v.values_mut().for_each(|w| *w /= norm);
+                v
+            })
+            .collect();
+
+        let mut postings: HashMap<String, Vec<(usize, f64)>> = HashMap::new();
+        for (i, v) in vectors.iter().enumerate() {
+            for (t, w) in v {
+                postings.entry(t.clone()).or_default().push((i, *w));
+            }
+        }
+
+        Similarity { vectors, postings }
+    }
+
+    /// Return the indices of the Zettel whose contents are similar to those of the `i`th one, along
+    /// with their similarity, from the most to the least similar
+    pub fn most_similar(&self, i: usize) -> Vec<(usize, f64)>
+    {
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for (t, w) in &self.vectors[i] {
+            for (j, other) in &self.postings[t] {
+                if *j != i {
+                    *scores.entry(*j).or_default() += w * other;
+                }
+            }
+        }
+        let mut scores: Vec<(usize, f64)> = scores.into_iter().collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scores
+    }
+}
+
+/// Return the MinHash signature of the text: the minimum hash of all its shingles (runs of
+/// `SHINGLE_SIZE` consecutive words), under `MINHASH_SIZE` different hash functions
+///
+/// The share of equal values in two signatures estimates how many shingles the two texts have in
+/// common (their Jaccard similarity). Empty texts have empty signatures.
+pub fn minhash(text: &str) -> Vec<u32>
+{
+    let words = tokenize(text);
+    if words.is_empty() {
+        return vec![];
+    }
+    let shingles: Vec<u64> = words
+        .windows(SHINGLE_SIZE.min(words.len()))
+        .map(|w| fnv1a(&w.join(" ")))
+        .collect();
+
+    (0..MINHASH_SIZE as u64)
+        .map(|seed| {
+            shingles
+                .iter()
+                .map(|h| splitmix64(h ^ splitmix64(seed)) as u32)
+                .min()
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Estimate how similar two texts are, from 0 to 1, given their MinHash signatures
+pub fn minhash_similarity(a: &[u32], b: &[u32]) -> f64
+{
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+/// Return the pairs of indices `(i, j)`, with `i < j`, of the signatures that may be at least
+/// `threshold` similar, without comparing every pair of them (through locality-sensitive hashing)
+///
+/// Signatures are cut into bands of consecutive values, and two signatures are a candidate pair if
+/// they're equal on any band. Bands are as wide as they can be while keeping to `CANDIDATE_RECALL`,
+/// so that few dissimilar pairs are candidates; the pairs are still to be checked with
+/// `minhash_similarity`. With a threshold of zero, every pair that's at all similar is a
+/// candidate.
+pub fn minhash_candidates(signatures: &[&[u32]], threshold: f64) -> Vec<(usize, usize)>
+{
+    let rows = [32, 16, 8, 4, 2]
+        .iter()
+        .copied()
+        .find(|&r| {
+            let bands = (MINHASH_SIZE / r) as i32;
+            1.0 - (1.0 - threshold.powi(r as i32)).powi(bands) >= CANDIDATE_RECALL
+        })
+        .unwrap_or(1);
+
+    let mut candidates = HashSet::new();
+    for band in (0..MINHASH_SIZE).step_by(rows) {
+        let mut buckets: HashMap<&[u32], Vec<usize>> = HashMap::new();
+        for (i, s) in signatures.iter().enumerate() {
+            if s.len() == MINHASH_SIZE {
+                buckets.entry(&s[band..band + rows]).or_default().push(i);
+            }
+        }
+        for bucket in buckets.values() {
+            for (k, i) in bucket.iter().enumerate() {
+                candidates.extend(bucket[k + 1..].iter().map(|j| (*i, *j)));
+            }
+        }
+    }
+    let mut candidates: Vec<(usize, usize)> = candidates.into_iter().collect();
+    candidates.sort();
+    candidates
+}
+
+/// The 64-bit FNV-1a hash; unlike the standard library's hasher, it's guaranteed to never change,
+/// which matters since signatures are stored in the database
+fn fnv1a(s: &str) -> u64
+{
+    s.bytes().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Scramble the bits of a number, so that every seed gives a different hash function
+fn splitmix64(x: u64) -> u64
+{
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Highlights the terms of a query within text
+struct Highlighter
+{
+    re: Regex,
+    start: &'static str,
+    end: &'static str,
+}
+
+impl Highlighter
+{
+    /// Highlight in bold when printing to a terminal, or in Markdown otherwise, unless `highlight`
+    /// isn't set, in which case the matches are left as they are
+    fn new(terms: &[String], highlight: bool) -> Self
+    {
+        let alternatives: Vec<String> = terms.iter().map(|t| regex::escape(t)).collect();
+        let re = Regex::new(&format!(r"(?i)\b({})\b", alternatives.join("|"))).unwrap();
+        let (start, end) = if !highlight {
+            ("", "")
+        } else if std::io::stdout().is_terminal() {
+            ("\x1b[1m", "\x1b[0m")
+        } else {
+            ("**", "**")
+        };
+        Highlighter { re, start, end }
+    }
+
+    /// Return a single line of text surrounding the first match in `contents`, with every match
+    /// highlighted, or an empty string if nothing matches
+    fn snippet(&self, contents: &str) -> String
+    {
+        let m = match self.re.find(contents) {
+            Some(m) => m,
+            None => return String::new(),
+        };
+
+        let mut start = m.start().saturating_sub(SNIPPET_CONTEXT);
+        while !contents.is_char_boundary(start) {
+            start -= 1;
+        }
+        let mut end = (m.end() + SNIPPET_CONTEXT).min(contents.len());
+        while !contents.is_char_boundary(end) {
+            end += 1;
+        }
+
+        // don't cut words in half, as long as the match itself is kept
+        if start > 0 {
+            if let Some(ws) = contents[start..m.start()].find(char::is_whitespace) {
+                start += ws;
+            }
+        }
+        if end < contents.len() {
+            if let Some(ws) = contents[m.end()..end].rfind(char::is_whitespace) {
+                end = m.end() + ws;
+            }
+        }
+        let excerpt = &contents[start..end];
+
+        let highlighted = self
+            .re
+            .replace_all(excerpt, format!("{}$1{}", self.start, self.end));
+        strip_multiple_whitespace(highlighted.trim())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn similar_contents_share_rare_words()
+    {
+        let contents: Vec<String> = [
+            "neurons fire when the synapse gets excited",
+            "the synapse gets excited, and neurons fire",
+            "the weather is nice today",
+            "the neurons",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+        let similarity = Similarity::from_contents(&contents);
+
+        let similar: Vec<usize> = similarity.most_similar(0).iter().map(|(j, _)| *j).collect();
+        // `the` is everywhere, so it doesn't make the weather any more similar
+        assert_eq!(similar, [1, 3]);
+        let (_, score) = similarity.most_similar(1)[0];
+        assert!(score > 0.0 && score <= 1.0 + 1e-9, "{}", score);
+    }
+
+    #[test]
+    fn minhash_signatures()
+    {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let signature = minhash(text);
+        assert_eq!(signature.len(), MINHASH_SIZE);
+        // signatures are stored in the database, so they must never change between runs
+        assert_eq!(
+            signature,
+            minhash("The quick, brown fox jumps over the lazy dog!")
+        );
+        assert!(minhash(" \n ").is_empty());
+        // notes that are shorter than a shingle still get a signature
+        assert_eq!(minhash("fox").len(), MINHASH_SIZE);
+    }
+
+    #[test]
+    fn minhash_estimates_similarity()
+    {
+        let a = minhash("the quick brown fox jumps over the lazy dog and runs into the woods");
+        let b = minhash("the quick brown fox jumps over the lazy cat and runs into the woods");
+        let c = minhash("completely unrelated words about neurons firing in the cortex");
+
+        assert_eq!(minhash_similarity(&a, &a), 1.0);
+        let similar = minhash_similarity(&a, &b);
+        let different = minhash_similarity(&a, &c);
+        assert!(similar > 0.3 && similar < 1.0, "{}", similar);
+        assert!(different < 0.1, "{}", different);
+        assert_eq!(minhash_similarity(&a, &[]), 0.0);
+        assert_eq!(minhash_similarity(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn candidates_are_the_similar_signatures()
+    {
+        let signatures = [
+            minhash("the quick brown fox jumps over the lazy dog and runs into the woods"),
+            minhash("completely unrelated words about neurons firing in the cortex"),
+            minhash("the quick brown fox jumps over the lazy dog and runs into the woods"),
+            minhash("the quick brown fox jumps over the lazy cat and runs into the woods"),
+            vec![],
+        ];
+        let signatures: Vec<&[u32]> = signatures.iter().map(|s| s.as_slice()).collect();
+
+        assert_eq!(minhash_candidates(&signatures, 1.0), [(0, 2)]);
+        // every pair that's at all similar is a candidate
+        let all: Vec<(usize, usize)> = (0..signatures.len())
+            .flat_map(|i| (i + 1..signatures.len()).map(move |j| (i, j)))
+            .filter(|(i, j)| minhash_similarity(signatures[*i], signatures[*j]) > 0.0)
+            .collect();
+        assert_eq!(minhash_candidates(&signatures, 0.0), all);
+        assert!(all.contains(&(0, 3)));
+    }
+
+    /// Rank the Zettel with the given titles and contents, and return the titles of the hits, best
+    /// first, along with their snippets
+    fn search(notes: &[(&str, &str)], query: &str, highlight: bool) -> Vec<(String, String)>
+    {
+        let zs: Vec<Zettel> = notes.iter().map(|(t, _)| Zettel::new(t, "")).collect();
+        let contents: Vec<String> = notes.iter().map(|(_, c)| c.to_string()).collect();
+        let mut hits = rank(&zs, &contents, query, highlight);
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.into_iter()
+            .map(|h| (h.zettel.title, h.snippet))
+            .collect()
+    }
+
+    fn titles(hits: &[(String, String)]) -> Vec<&str>
+    {
+        hits.iter().map(|(t, _)| t.as_str()).collect()
+    }
+
+    #[test]
+    fn more_matches_rank_higher()
+    {
+        let notes = [
+            ("One", "a neuron fires, and so do others"),
+            ("Two", "a neuron fires, and another neuron"),
+            ("None", "nothing to see here, move along"),
+        ];
+        assert_eq!(titles(&search(&notes, "neuron", false)), ["Two", "One"]);
+        assert!(search(&notes, "  ", false).is_empty());
+    }
+
+    #[test]
+    fn titles_are_boosted()
+    {
+        let notes = [
+            ("Notes", "about synapses and how cells talk"),
+            ("Synapses", "about notes and how cells talk"),
+        ];
+        assert_eq!(
+            titles(&search(&notes, "synapses", false)),
+            ["Synapses", "Notes"]
+        );
+    }
+
+    #[test]
+    fn long_titles_count_towards_the_length()
+    {
+        let notes = [
+            (
+                "A very long title with a lot of words in it",
+                "an apple pie",
+            ),
+            ("Short", "an apple pie"),
+        ];
+        assert_eq!(
+            titles(&search(&notes, "apple", false)),
+            ["Short", "A very long title with a lot of words in it"]
+        );
+    }
+
+    #[test]
+    fn snippets_are_only_highlighted_when_asked()
+    {
+        let notes = [("Fruit", "an Apple   pie\nwith apples")];
+        let plain = search(&notes, "apple", false);
+        assert_eq!(plain[0].1, "an Apple pie with apples");
+
+        let highlighted = search(&notes, "apple", true);
+        assert_ne!(highlighted[0].1, plain[0].1);
+        assert!(highlighted[0].1.ends_with("pie with apples"));
+    }
+}
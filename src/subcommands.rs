@@ -1,103 +1,34 @@
 use clap::ArgMatches;
 use clap_complete::Shell::*;
 use clap_complete_nushell::Nushell;
+use rayon::prelude::*;
 use regex::Regex;
 use rusqlite::Error;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::ops::RangeInclusive;
 
 use crate::config::ConfigOptions;
-use crate::graph::{vizk, zk_graph_dot_output, zk_graph_json_output};
+use crate::graph::{
+    centrality as graph_centrality, clusters as graph_clusters, cycles as graph_cycles,
+    neighbourhood, paths_to_zettel, related, shortest_paths, strongly_connected, subgraph,
+    subgraphs_dot_output, vizk, zk_graph_csv_edges_output, zk_graph_csv_nodes_output,
+    zk_graph_dot_output, zk_graph_gexf_output, zk_graph_graphml_output, zk_graph_json_output,
+    zk_graph_mermaid_output, zk_graph_svg_output, Centrality, Clustering, Direction, GraphOptions,
+    Subgraph,
+};
 use crate::zettel::strip_multiple_whitespace;
 use crate::Database;
 use crate::Zettel;
 
 use crate::cli;
 use crate::io::{abs_path, file_exists};
+use crate::printer::{Output, Printer, SortKey};
+use crate::search::{minhash_candidates, minhash_similarity, ranked_search, Similarity};
 
-/// A printer that prints. Because it's convenient.
-struct Printer
-{
-    zettel: Vec<Zettel>,
-    additional: Vec<String>,
-    // Print according to a certain format, replacing the following placeholder tokens
-    //
-    //  %t - title
-    //  %p - project
-    //  %P - path
-    //  %l - (forward) links
-    //  %b - backlinks
-    //  %a - contents of the `additional` field (--text flag fills this with the matched pattern)
-    format: String,
-    link_separator: String,
-}
-
-impl Printer
-{
-    fn set_zettelkasten(&mut self, new_zettel: Vec<Zettel>)
-    {
-        self.zettel = new_zettel;
-    }
-
-    fn set_additional(&mut self, new_additional: Vec<String>)
-    {
-        self.additional = new_additional;
-    }
-
-    fn set_format(&mut self, new_format: String)
-    {
-        self.format = new_format;
-    }
-
-    fn set_link_separator(&mut self, new_separator: String)
-    {
-        self.link_separator = new_separator;
-    }
-
-    fn print_one(&mut self, cfg: &ConfigOptions, zettel: Zettel)
-    {
-        self.zettel = vec![zettel];
-        self.print(cfg);
-    }
-
-    /// Abracadabra, yadda yadda. Print everything properly.
-    fn print(&mut self, cfg: &ConfigOptions)
-    {
-        // basically. since there's a `zip()` call, if the `additional` vector is smaller in length
-        // than the `zettel` vector, then some things won't get printed.
-        // this really only makes sure that everything gets printed
-        let len_diff = self.zettel.len() - self.additional.len();
-        let mut empty_diff = vec!["".to_string(); len_diff];
-        self.additional.append(&mut empty_diff);
-
-        let mut zip = self.zettel.iter().zip(&self.additional).collect::<Vec<_>>();
-        zip.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-        for (z, a) in zip {
-            let mut result = self.format.to_string();
-
-            result = result.replace("%t", &z.title);
-            result = result.replace("%p", &z.project);
-            result = result.replace("%P", &z.filename(cfg));
-            result = result.replace("%l", &z.links.join(&self.link_separator));
-            result = result.replace("%a", a);
-            result = result.replace("%b", &z.backlinks.join(&self.link_separator));
-
-            println!("{}", result);
-        }
-    }
-}
-
-impl Default for Printer
-{
-    fn default() -> Printer
-    {
-        Printer {
-            zettel: vec![],
-            additional: vec![],
-            format: "[%p] %t".to_string(),
-            link_separator: "|".to_string(),
-        }
-    }
-}
+/// The number of results returned by `query --search`, unless asked otherwise
+const SEARCH_RESULTS: usize = 10;
 
 pub fn sync(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
 {
@@ -130,11 +61,173 @@ pub fn sync(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
 /// Query the database, applying various filters if proivded
 pub fn query(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
 {
+    if let Some(saved) = matches.get_one::<String>("SAVED_QUERY") {
+        return saved_query(saved, matches, cfg);
+    }
+
     let db = Database::new(&cfg.db_file())?;
     db.init()?;
 
-    let mut zs: Vec<Zettel> = db.all()?;
     let mut printer = Printer::default();
+    let mut zs = filter(matches, cfg, &db, &mut printer)?;
+    let output = match matches.get_one::<String>("OUTPUT").map(|o| o.as_str()) {
+        Some("json") => Output::Json,
+        Some("ndjson") => Output::Ndjson,
+        Some("csv") => Output::Csv,
+        Some("tsv") => Output::Tsv,
+        _ => Output::Format,
+    };
+
+    if let Some(terms) = matches.get_one::<String>("SEARCH") {
+        // the structured formats are meant for other programs, which have no use for the markup
+        let highlight = matches!(output, Output::Format);
+        let hits = ranked_search(cfg, &zs, terms, highlight);
+        let mut scores = HashMap::new();
+        let mut snippets = HashMap::new();
+        zs = hits
+            .into_iter()
+            .map(|h| {
+                scores.insert(h.zettel.title.clone(), h.score);
+                snippets.insert(h.zettel.title.clone(), h.snippet);
+                h.zettel
+            })
+            .collect();
+        printer.set_scores(scores);
+        printer.set_additional(snippets);
+        printer.set_format("[%p] %t (%s)%(\n\t%a%)".to_string());
+    }
+
+    if let Some(related_to) = matches.get_one::<String>("RELATED") {
+        let all = db.all()?;
+        let start = filter_title(all.clone(), related_to, matches.get_flag("EXACT_MATCH"));
+        let scores = related(&all, &start);
+        zs.retain(|z| scores.contains_key(&z.title));
+        printer.set_scores(scores);
+        printer.set_format("[%p] %t (%s)".to_string());
+    }
+
+    if let Some(ends) = matches.get_many::<String>("PATH") {
+        let ends: Vec<&String> = ends.collect();
+        let count = *matches.get_one::<usize>("PATH_COUNT").unwrap_or(&1);
+        let paths = shortest_paths(&zs, ends[0], ends[1], count, matches.get_flag("UNDIRECTED"));
+        if paths.is_empty() {
+            eprintln!("error: no path from '{}' to '{}'", ends[0], ends[1]);
+            return Ok(());
+        }
+        // a path may be either printed as a chain of titles or turned into a graph
+        if matches.contains_id("GRAPH") {
+            zs = paths_to_zettel(&zs, &paths);
+        } else {
+            paths.iter().for_each(|p| println!("{}", p.join(" -> ")));
+            return Ok(());
+        }
+    }
+
+    let searching = matches.contains_id("SEARCH");
+    let ranking = searching || matches.contains_id("RELATED");
+    let sort_by = match matches.get_one::<String>("SORT").map(|s| s.as_str()) {
+        Some("project") => SortKey::Project,
+        Some("links") => SortKey::Links,
+        Some("backlinks") => SortKey::Backlinks,
+        Some("tags") => SortKey::Tags,
+        Some("mtime") => SortKey::Modified,
+        Some("score") => SortKey::Score,
+        Some(_) => SortKey::Title,
+        // the most relevant results come first, unless asked otherwise
+        None if ranking => SortKey::Score,
+        None => SortKey::Title,
+    };
+    let default_order = ranking && !matches.contains_id("SORT");
+    let reverse = matches.get_flag("REVERSE") != default_order;
+    let limit = match matches.get_one::<usize>("LIMIT") {
+        Some(limit) => Some(*limit),
+        None if searching => Some(SEARCH_RESULTS),
+        None => None,
+    };
+    printer.set_order(sort_by, reverse, limit);
+    printer.set_output(output);
+
+    if matches.get_flag("GREP") {
+        let text = matches.get_one::<String>("TEXT_REGEX").unwrap();
+        let context = *matches.get_one::<usize>("CONTEXT").unwrap_or(&0);
+        printer.set_zettelkasten(zs);
+        print_matching_lines(cfg, printer.ordered(), text, context);
+        return Ok(());
+    }
+
+    if let Some(graph) = matches.get_one::<String>("GRAPH") {
+        let mode = match matches.get_one::<String>("SUBGRAPH").map(|m| m.as_str()) {
+            Some("induced") => Subgraph::Induced,
+            Some("expanded") => Subgraph::Expanded(*matches.get_one::<usize>("HOPS").unwrap_or(&1)),
+            _ => Subgraph::Linked,
+        };
+        printer.set_zettelkasten(zs);
+        // only the expanded subgraph looks beyond the results
+        let all = match mode {
+            Subgraph::Expanded(_) => db.all()?,
+            _ => vec![],
+        };
+        let (zs, context) = subgraph(&all, printer.ordered(), mode);
+        let zs = &zs;
+        let opts = GraphOptions {
+            context,
+            by_project: matches.get_flag("BY_PROJECT"),
+            tags: matches.get_flag("WITH_TAGS"),
+            style: cfg.graph_style.clone(),
+            existing: Some(db.list_titles()?.into_iter().collect()),
+        };
+        match graph.as_str() {
+            "vizk" => vizk(zs, &opts, None),
+            "dot" => zk_graph_dot_output(zs, &opts),
+            "json" => zk_graph_json_output(zs, &opts),
+            "graphml" => zk_graph_graphml_output(zs, &opts),
+            "gexf" => zk_graph_gexf_output(zs, &opts),
+            "csv-nodes" => zk_graph_csv_nodes_output(zs, &opts),
+            "csv-edges" => zk_graph_csv_edges_output(zs, &opts),
+            "mermaid" => zk_graph_mermaid_output(zs, &opts),
+            "svg" => zk_graph_svg_output(zs, &opts),
+            _ => {
+                eprintln!(
+                    "error: expected one of 'json', 'dot', 'vizk', 'graphml', 'gexf', 'csv-nodes', \
+                     'csv-edges', 'mermaid', 'svg' (got: '{}')",
+                    graph
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(format) = matches.get_one::<String>("FORMAT") {
+        let link_sep = matches
+            .get_one::<String>("LINK_SEP")
+            .map_or(" | ", |s| s.as_str());
+        printer.set_format(replace_literals(format));
+        printer.set_link_separator(replace_literals(link_sep));
+    }
+    if let Some(header) = matches.get_one::<String>("HEADER") {
+        printer.set_header(replace_literals(header));
+    }
+    if let Some(footer) = matches.get_one::<String>("FOOTER") {
+        printer.set_footer(replace_literals(footer));
+    }
+
+    printer.set_zettelkasten(zs);
+
+    printer.print(cfg);
+
+    Ok(())
+}
+
+/// Keep only the Zettel that match the filtering options shared by `query` and `pick`, handing
+/// whatever the filters find out about them (e.g. `--text` matches) over to the printer
+fn filter(
+    matches: &ArgMatches,
+    cfg: &ConfigOptions,
+    db: &Database,
+    printer: &mut Printer,
+) -> Result<Vec<Zettel>, Error>
+{
+    let mut zs: Vec<Zettel> = db.all()?;
 
     let exact = matches.get_flag("EXACT_MATCH");
 
@@ -155,49 +248,459 @@ pub fn query(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
     }
     if let Some(text) = matches.get_one::<String>("TEXT_REGEX") {
         let vs = filter_text(zs.clone(), text, cfg);
-        let mut texts: Vec<String> = vec![];
+        let mut texts = HashMap::new();
         let mut found = vec![];
-        // maybe speed this up by using unzip? I digress
         for (z, t) in vs {
+            texts.insert(z.title.clone(), t);
             found.push(z);
-            texts.push(t);
         }
         printer.set_additional(texts);
         zs = intersect(&zs, &found);
     }
+    if let Some(near) = matches.get_one::<String>("NEAR") {
+        let depth = *matches.get_one::<usize>("DEPTH").unwrap_or(&1);
+        let direction = match matches.get_one::<String>("DIRECTION").map(|d| d.as_str()) {
+            Some("out") => Direction::Out,
+            Some("in") => Direction::In,
+            _ => Direction::Both,
+        };
+        let all = db.all()?;
+        let start = filter_title(all.clone(), near, exact);
+        let distances = neighbourhood(&all, &start, depth, direction);
+        zs.retain(|z| distances.contains_key(&z.title));
+        printer.set_distances(distances);
+    }
 
-    if matches.get_flag("LONERS") {
-        zs = filter_isolated(zs);
+    if let Some(since) = matches.get_one::<i64>("MODIFIED_SINCE") {
+        zs.retain(|z| z.modified >= *since);
+    }
+    if let Some(before) = matches.get_one::<i64>("MODIFIED_BEFORE") {
+        zs.retain(|z| z.modified < *before);
+    }
+    if let Some(since) = matches.get_one::<i64>("CREATED_SINCE") {
+        zs.retain(|z| z.created >= *since);
+    }
+    if let Some(before) = matches.get_one::<i64>("CREATED_BEFORE") {
+        zs.retain(|z| z.created < *before);
     }
 
-    if let Some(graph) = matches.get_one::<String>("GRAPH") {
-        match graph.as_str() {
-            "vizk" => vizk(&zs),
-            "dot" => zk_graph_dot_output(&zs),
-            "json" => zk_graph_json_output(&zs),
-            _ => {
-                eprintln!(
-                    "error: expected one of 'json', 'dot', 'vizk' (got: '{}')",
-                    graph
-                );
-            }
+    let loners = matches.get_flag("LONERS");
+    let min_links = *matches.get_one::<usize>("MIN_LINKS").unwrap_or(&0);
+    let mut max_links = *matches.get_one::<usize>("MAX_LINKS").unwrap_or(&usize::MAX);
+    if loners || matches.get_flag("DEAD_ENDS") {
+        max_links = 0;
+    }
+    let min_backlinks = *matches.get_one::<usize>("MIN_BACKLINKS").unwrap_or(&0);
+    let mut max_backlinks = *matches
+        .get_one::<usize>("MAX_BACKLINKS")
+        .unwrap_or(&usize::MAX);
+    if loners || matches.get_flag("SOURCES") {
+        max_backlinks = 0;
+    }
+    zs = filter_degree(zs, min_links..=max_links, min_backlinks..=max_backlinks);
+    if matches.get_flag("GHOST_LINKS") {
+        zs = filter_ghost_links(zs, &db.all()?);
+    }
+
+    Ok(zs)
+}
+
+/// The reasons why `pick` fails to pick a note
+#[derive(Debug)]
+pub enum PickError
+{
+    Database(Error),
+    /// There's no terminal to show the finder in
+    NoTerminal,
+    /// None of the notes match the filters, so there's nothing to pick from
+    NoMatches,
+    /// The finder itself failed
+    Finder(io::Error),
+}
+
+impl fmt::Display for PickError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            PickError::Database(e) => write!(f, "{}", e),
+            PickError::NoTerminal => write!(f, "pick needs a terminal to show the finder in"),
+            PickError::NoMatches => write!(f, "no notes match the given options"),
+            PickError::Finder(e) => write!(f, "couldn't pick a note: {}", e),
         }
-        return Ok(());
     }
+}
+
+impl From<Error> for PickError
+{
+    fn from(e: Error) -> Self
+    {
+        PickError::Database(e)
+    }
+}
+
+/// Let the user pick one of the Zettel that match the filters through a fuzzy finder, then print
+/// its path, or print it according to `--format`
+pub fn pick(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), PickError>
+{
+    let db = Database::new(&cfg.db_file())?;
+    db.init()?;
+
+    // the finder can't read keys from a pipe, so it would wait forever if it were given one
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Err(PickError::NoTerminal);
+    }
+
+    let mut printer = Printer::default();
+    let mut zs = filter(matches, cfg, &db, &mut printer)?;
+    zs.sort();
+    if zs.is_empty() {
+        return Err(PickError::NoMatches);
+    }
+
+    let items: Vec<String> = zs
+        .iter()
+        .map(|z| format!("[{}] {}", z.project, z.title))
+        .collect();
+    // the finder is drawn on stderr, so that only the result is printed to stdout
+    let choice = match dialoguer::FuzzySelect::new()
+        .items(&items)
+        .default(0)
+        .interact_opt()
+    {
+        Ok(Some(choice)) => choice,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(PickError::Finder(e)),
+    };
+    let z = zs.swap_remove(choice);
 
     if let Some(format) = matches.get_one::<String>("FORMAT") {
-        let link_sep = matches.get_one::<&str>("LINK_SEP").unwrap_or(&" | ");
+        let link_sep = matches
+            .get_one::<String>("LINK_SEP")
+            .map_or(" | ", |s| s.as_str());
         printer.set_format(replace_literals(format));
         printer.set_link_separator(replace_literals(link_sep));
+        printer.print_one(cfg, z);
+    } else {
+        println!("{}", abs_path(&z.filename(cfg)));
     }
 
-    printer.set_zettelkasten(zs);
+    Ok(())
+}
 
-    printer.print(cfg);
+/// Suggest links between the notes whose contents are the most similar, but that aren't linked in
+/// either direction yet
+pub fn suggest(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
+{
+    let db = Database::new(&cfg.db_file())?;
+    db.init()?;
+
+    let mut zs = db.all()?;
+    zs.sort();
+    let limit = *matches.get_one::<usize>("LIMIT").unwrap_or(&10);
+    let linked = |a: &Zettel, b: &Zettel| a.links.contains(&b.title) || b.links.contains(&a.title);
+    let similarity = Similarity::new(cfg, &zs);
+
+    if let Some(title) = matches.get_one::<String>("TITLE") {
+        let i = match zs.iter().position(|z| &z.title == title) {
+            Some(i) => i,
+            None => {
+                eprintln!("error: no note titled '{}'", title);
+                return Ok(());
+            }
+        };
+        let mut printer = Printer::default();
+        let mut scores = HashMap::new();
+        let mut suggested = vec![];
+        for (j, score) in similarity.most_similar(i) {
+            if !linked(&zs[i], &zs[j]) {
+                scores.insert(zs[j].title.clone(), score);
+                suggested.push(zs[j].clone());
+            }
+        }
+        printer.set_zettelkasten(suggested);
+        printer.set_scores(scores);
+        printer.set_format("[%p] %t (%s)".to_string());
+        if let Some(format) = matches.get_one::<String>("FORMAT") {
+            let link_sep = matches
+                .get_one::<String>("LINK_SEP")
+                .map_or(" | ", |s| s.as_str());
+            printer.set_format(replace_literals(format));
+            printer.set_link_separator(replace_literals(link_sep));
+        }
+        printer.set_order(SortKey::Score, true, Some(limit));
+        printer.print(cfg);
+        return Ok(());
+    }
+
+    // every pair is found twice, so only keep the one whose first note comes first; and, since
+    // only the most similar pairs are printed, only the most similar notes to each note are needed
+    let (zs, linked, similarity) = (&zs, &linked, &similarity);
+    let mut pairs: Vec<(usize, usize, f64)> = (0..zs.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            similarity
+                .most_similar(i)
+                .into_iter()
+                .filter(move |(j, _)| i < *j && !linked(&zs[i], &zs[*j]))
+                .take(limit)
+                .map(move |(j, score)| (i, j, score))
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.2.total_cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+    for (i, j, score) in pairs.iter().take(limit) {
+        println!("{} <-> {} ({:.2})", zs[*i].title, zs[*j].title, score);
+    }
+
+    Ok(())
+}
+
+/// Print the pairs of notes whose contents are at least as similar as the threshold, according to
+/// the fingerprints stored in the database
+pub fn duplicates(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
+{
+    let threshold = *matches.get_one::<f64>("THRESHOLD").unwrap_or(&0.8);
+    if !(0.0..=1.0).contains(&threshold) {
+        eprintln!("error: the threshold must be between 0 and 1");
+        return Ok(());
+    }
+
+    let db = Database::new(&cfg.db_file())?;
+    db.init()?;
+
+    let mut zs = db.all()?;
+    zs.sort();
+    let signatures: Vec<&[u32]> = zs.iter().map(|z| z.fingerprint.as_slice()).collect();
+    let mut pairs: Vec<(usize, usize, f64)> = minhash_candidates(&signatures, threshold)
+        .into_par_iter()
+        .filter_map(|(i, j)| {
+            let similarity = minhash_similarity(signatures[i], signatures[j]);
+            (similarity > 0.0 && similarity >= threshold).then_some((i, j, similarity))
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.2.total_cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+    for (i, j, similarity) in pairs {
+        println!("{} <-> {} ({:.2})", zs[i].title, zs[j].title, similarity);
+    }
+
+    Ok(())
+}
+
+/// Rank the Zettel that match the filters by how central they are in the graph that they make up
+pub fn centrality(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
+{
+    let db = Database::new(&cfg.db_file())?;
+    db.init()?;
+
+    let zs = filter(matches, cfg, &db, &mut Printer::default())?;
+    let metric = matches
+        .get_one::<String>("SORT")
+        .map_or("pagerank", |m| m.as_str());
+    let score = |c: &Centrality| match metric {
+        "betweenness" => c.betweenness,
+        "in" => c.in_degree as f64,
+        "out" => c.out_degree as f64,
+        _ => c.pagerank,
+    };
+
+    let existing: HashSet<String> = db.list_titles()?.into_iter().collect();
+    let mut ranked = graph_centrality(&zs, Some(&existing));
+    ranked.sort_by(|a, b| score(b).total_cmp(&score(a)).then(a.title.cmp(&b.title)));
+    if let Some(limit) = matches.get_one::<usize>("LIMIT") {
+        ranked.truncate(*limit);
+    }
+
+    match matches.get_one::<String>("OUTPUT").map(|o| o.as_str()) {
+        Some("json") => println!("{}", serde_json::to_string(&ranked).unwrap()),
+        Some("vizk") => {
+            // scale the scores so that the most central note is about as big as a note with 20
+            // links would be
+            let max = ranked.iter().map(score).fold(0.0, f64::max);
+            let sizes: HashMap<String, f64> = ranked
+                .iter()
+                .map(|c| {
+                    let size = if max > 0.0 {
+                        1.0 + 19.0 * score(c) / max
+                    } else {
+                        1.0
+                    };
+                    (c.title.clone(), size)
+                })
+                .collect();
+            let opts = GraphOptions {
+                existing: Some(existing),
+                ..Default::default()
+            };
+            vizk(&zs, &opts, Some(&sizes));
+        }
+        _ => {
+            println!(
+                "{:>10} {:>12} {:>5} {:>5}  TITLE",
+                "PAGERANK", "BETWEENNESS", "IN", "OUT"
+            );
+            for c in &ranked {
+                println!(
+                    "{:>10.4} {:>12.4} {:>5} {:>5}  {}{}",
+                    c.pagerank,
+                    c.betweenness,
+                    c.in_degree,
+                    c.out_degree,
+                    c.title,
+                    if c.ghost { " (ghost)" } else { "" }
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Group the Zettel that match the filters into clusters of closely linked notes
+pub fn clusters(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
+{
+    let db = Database::new(&cfg.db_file())?;
+    db.init()?;
+
+    let zs = filter(matches, cfg, &db, &mut Printer::default())?;
+    let method = match matches.get_one::<String>("METHOD").map(|m| m.as_str()) {
+        Some("components") => Clustering::Components,
+        _ => Clustering::Louvain,
+    };
+    let min_size = *matches.get_one::<usize>("MIN_SIZE").unwrap_or(&2);
+    let existing: HashSet<String> = db.list_titles()?.into_iter().collect();
+    let mut clusters = graph_clusters(&zs, Some(&existing), method);
+    clusters.retain(|c| c.members.len() >= min_size);
+
+    if matches.get_one::<String>("OUTPUT").map(|o| o.as_str()) == Some("json") {
+        println!("{}", serde_json::to_string(&clusters).unwrap());
+        return Ok(());
+    }
+    for (i, c) in clusters.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let count = c.members.len();
+        let notes = if count == 1 { "note" } else { "notes" };
+        println!(
+            "Cluster {} ({} {}, most central: {})",
+            i + 1,
+            count,
+            notes,
+            c.central
+        );
+        for m in &c.members {
+            println!("\t{}", m);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the cycles of links among the Zettel that match the filters, or the strongly connected
+/// components that they make up
+pub fn cycles(matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
+{
+    let db = Database::new(&cfg.db_file())?;
+    db.init()?;
+
+    let zs = filter(matches, cfg, &db, &mut Printer::default())?;
+    let components = matches.get_flag("COMPONENTS");
+    let existing: HashSet<String> = db.list_titles()?.into_iter().collect();
+    let groups = if components {
+        strongly_connected(&zs, Some(&existing))
+    } else {
+        let max_length = *matches.get_one::<usize>("MAX_LENGTH").unwrap_or(&5);
+        graph_cycles(&zs, Some(&existing), max_length)
+    };
+
+    if matches.get_one::<String>("OUTPUT").map(|o| o.as_str()) == Some("dot") {
+        let name = if components { "Component" } else { "Cycle" };
+        subgraphs_dot_output(&zs, &groups, name, !components);
+    } else if components {
+        for (i, c) in groups.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            let notes = if c.len() == 1 { "note" } else { "notes" };
+            println!("Component {} ({} {})", i + 1, c.len(), notes);
+            for title in c {
+                println!("\t{}", title);
+            }
+        }
+    } else {
+        for c in &groups {
+            println!("{} -> {}", c.join(" -> "), c[0]);
+        }
+    }
 
     Ok(())
 }
 
+/// Print every line of the Zettel that matches the pattern, in the `path:line:text` format, along
+/// with `context` lines before and after it, in the `path-line-text` format
+///
+/// Just like `grep`, groups of lines that aren't next to each other are separated by `--` when
+/// context is printed.
+fn print_matching_lines(cfg: &ConfigOptions, zs: &[Zettel], pattern: &str, context: usize)
+{
+    let mut first_group = true;
+    for z in zs {
+        let path = z.filename(cfg);
+        let (matching, lines) = z.find_pattern_lines(cfg, pattern);
+
+        let mut last_printed: Option<usize> = None;
+        for m in &matching {
+            let start = m.saturating_sub(context).max(1);
+            let end = (m + context).min(lines.len());
+            // start a new group unless it overlaps with or follows right after the previous one
+            let start = match last_printed {
+                Some(last) if last + 1 >= start => last + 1,
+                _ => {
+                    if context > 0 && !first_group {
+                        println!("--");
+                    }
+                    first_group = false;
+                    start
+                }
+            };
+            for n in start..=end {
+                let separator = if matching.contains(&n) { ':' } else { '-' };
+                println!("{}{}{}{}{}", path, separator, n, separator, lines[n - 1]);
+            }
+            last_printed = Some(end.max(last_printed.unwrap_or(0)));
+        }
+    }
+}
+
+/// Run the query saved in the configuration file under the given name (prefixed by `@`), adding
+/// the options given on the command line on top of it
+fn saved_query(name: &str, matches: &ArgMatches, cfg: &ConfigOptions) -> Result<(), Error>
+{
+    let saved_args = match name.strip_prefix('@').and_then(|n| cfg.saved_query_args(n)) {
+        Some(args) => args,
+        None => {
+            eprintln!("error: no saved query called '{}'", name);
+            return Ok(());
+        }
+    };
+
+    let matches = saved_query_matches(saved_args, matches);
+    query(matches.subcommand_matches("query").unwrap(), cfg)
+}
+
+/// Parse the options of a saved query along with the ones given to `query` on the command line
+///
+/// The saved options come first, so that the ones on the command line take precedence.
+fn saved_query_matches(saved_args: Vec<String>, matches: &ArgMatches) -> ArgMatches
+{
+    let app = cli::build();
+    let query = app.find_subcommand("query").unwrap();
+    let mut args = vec![app.get_name().to_string(), query.get_name().to_string()];
+    args.extend(saved_args);
+    args.extend(cli::given_args(query, matches));
+    app.get_matches_from(args)
+}
+
 fn replace_literals(s: &str) -> String
 {
     s.replace(r"\n", "\n").replace(r"\t", "\t")
@@ -242,7 +745,7 @@ pub fn compl(matches: &ArgMatches) -> Result<(), Error>
         // Note that Nushell is handled separately since the Enum types don't match
     };
 
-    let app = &mut cli::build();
+    let app = &mut cli::build_with_saved_queries(&ConfigOptions::saved_query_names());
     if let Some(sh) = sh {
         clap_complete::generate(sh, app, app.get_name().to_string(), &mut std::io::stdout());
     } else if shell.as_str() == "nu" || shell.as_str() == "nushell" {
@@ -324,21 +827,33 @@ fn filter_tag(zs: Vec<Zettel>, pattern: &str, exact: bool) -> Vec<Zettel>
         .collect()
 }
 
-/// Keep only those Zettel that neither link to other notes, nor have links pointing to them
-fn filter_isolated(zs: Vec<Zettel>) -> Vec<Zettel>
+/// Keep only those Zettel whose numbers of links and backlinks are within the given ranges
+fn filter_degree(
+    zs: Vec<Zettel>,
+    links: RangeInclusive<usize>,
+    backlinks: RangeInclusive<usize>,
+) -> Vec<Zettel>
+{
+    zs.into_iter()
+        .filter(|z| links.contains(&z.links.len()) && backlinks.contains(&z.backlinks.len()))
+        .collect()
+}
+
+/// Keep only those Zettel that have links, all of which point to notes that don't exist (yet)
+fn filter_ghost_links(zs: Vec<Zettel>, all: &[Zettel]) -> Vec<Zettel>
 {
+    let titles: HashSet<&str> = all.iter().map(|z| z.title.as_str()).collect();
     zs.into_iter()
-        .filter(|z| z.links.is_empty() && z.backlinks.is_empty())
+        .filter(|z| !z.links.is_empty() && z.links.iter().all(|l| !titles.contains(l.as_str())))
         .collect()
 }
 
 /// Keep only the Zettel that are both in A and B
 fn intersect<T: Eq + Clone>(a: &[T], b: &[T]) -> Vec<T>
 {
-    a.to_owned()
-        .iter()
-        .cloned()
+    a.iter()
         .filter(|z| b.contains(z))
+        .cloned()
         .collect::<Vec<_>>()
 }
 
@@ -349,7 +864,6 @@ fn fwlinks(all: &[Zettel], linked_from: &str, exact: bool) -> Vec<Zettel>
     // them
     let fwlinks = &filter_title(all.to_owned(), linked_from, exact);
     all.iter()
-        .cloned()
         .filter(|z| {
             for fw in fwlinks {
                 if fw.links.contains(&z.title) {
@@ -358,6 +872,7 @@ fn fwlinks(all: &[Zettel], linked_from: &str, exact: bool) -> Vec<Zettel>
             }
             false
         })
+        .cloned()
         .collect()
 }
 
@@ -366,7 +881,6 @@ fn backlinks(all: &[Zettel], links_to: &str, exact: bool) -> Vec<Zettel>
 {
     let re = Regex::new(&format!("^{}$", links_to)).unwrap();
     all.iter()
-        .cloned()
         .filter(|z| {
             for l in z.links.clone() {
                 if links_to == l || (!exact && re.is_match(&l)) {
@@ -375,6 +889,7 @@ fn backlinks(all: &[Zettel], links_to: &str, exact: bool) -> Vec<Zettel>
             }
             false
         })
+        .cloned()
         .collect()
 }
 
@@ -417,6 +932,12 @@ fn create(cfg: &ConfigOptions, title: &str, project: &str) -> Result<(), Error>
         zettel.create(cfg);
         Printer::default().print_one(cfg, zettel.clone());
     }
+    // take the metadata from the file, if there is one
+    let zettel = if file_exists(&zettel.filename(cfg)) {
+        Zettel::from_file(cfg, &zettel.filename(cfg))
+    } else {
+        zettel
+    };
     db.save(&zettel)?;
 
     Ok(())
@@ -437,7 +958,7 @@ fn rename(cfg: &ConfigOptions, old_title: &str, new_title: &str) -> Result<(), E
 
     let results = db.find_by_title(old_title)?;
 
-    let old_zettel = if results.first().is_none() {
+    let old_zettel = if results.is_empty() {
         eprintln!("error: no Zettel with that title");
         return Ok(());
     } else {
@@ -447,11 +968,11 @@ fn rename(cfg: &ConfigOptions, old_title: &str, new_title: &str) -> Result<(), E
 
     if !file_exists(&old_zettel.filename(cfg)) {
         eprintln!("error: the Zettel does not exist on the filesystem");
-        db.delete(&old_zettel)?;
+        db.delete(old_zettel)?;
         return Ok(());
     }
 
-    let exists_in_db = db.find_by_title(new_title)?.first().is_some();
+    let exists_in_db = !db.find_by_title(new_title)?.is_empty();
     let exists_in_fs = file_exists(&new_zettel.filename(cfg));
     if exists_in_db || exists_in_fs {
         eprintln!("error: a note with the new title already exists: won't overwrite");
@@ -511,11 +1032,8 @@ fn mv(cfg: &ConfigOptions, pattern: &str, project: &str) -> Result<(), Error>
     if prompt.interact().unwrap_or_default() {
         crate::io::mkdir(&format!("{}/{}", cfg.zettelkasten, project));
         let new_notes = zs.iter().map(|z| Zettel {
-            title: z.title.clone(),
             project: project.to_string(),
-            links: z.links.clone(),
-            backlinks: z.backlinks.clone(),
-            tags: z.tags.clone(),
+            ..z.clone()
         });
         let pairs = zs.iter().zip(new_notes);
         pairs.for_each(|(old, new)| {
@@ -553,9 +1071,16 @@ fn generate(cfg: &ConfigOptions) -> Result<(), Error>
 {
     let start = std::time::Instant::now();
 
+    // the notes keep the creation times they had, as their files may have been replaced since
+    let created = {
+        let db = Database::new(&cfg.db_file())?;
+        db.init()?;
+        db.creation_times(None)?
+    };
+
     let mem_db = Database::new_in_memory(&cfg.db_file())?;
     mem_db.init()?;
-    mem_db.generate(cfg)?;
+    mem_db.generate(cfg, &created)?;
     mem_db.write_to(&cfg.db_file())?;
 
     println!(
@@ -575,3 +1100,129 @@ fn realproject(project: &str) -> &str
         any => any,        // any other project
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Make a note with the given links and backlinks
+    fn zettel(title: &str, links: &[&str], backlinks: &[&str]) -> Zettel
+    {
+        let mut z = Zettel::new(title, "");
+        z.links = links.iter().map(|l| l.to_string()).collect();
+        z.backlinks = backlinks.iter().map(|b| b.to_string()).collect();
+        z
+    }
+
+    fn titles(zs: &[Zettel]) -> Vec<&str>
+    {
+        zs.iter().map(|z| z.title.as_str()).collect()
+    }
+
+    /// A -> B -> C, D on its own, and E linking only to a ghost
+    fn zettelkasten() -> Vec<Zettel>
+    {
+        vec![
+            zettel("A", &["B"], &[]),
+            zettel("B", &["C"], &["A"]),
+            zettel("C", &[], &["B"]),
+            zettel("D", &[], &[]),
+            zettel("E", &["Ghost"], &[]),
+        ]
+    }
+
+    #[test]
+    fn filter_by_degree()
+    {
+        let zs = zettelkasten();
+        // loners
+        let kept = filter_degree(zs.clone(), 0..=0, 0..=0);
+        assert_eq!(titles(&kept), ["D"]);
+        // dead ends
+        let kept = filter_degree(zs.clone(), 0..=0, 0..=usize::MAX);
+        assert_eq!(titles(&kept), ["C", "D"]);
+        // sources
+        let kept = filter_degree(zs.clone(), 0..=usize::MAX, 0..=0);
+        assert_eq!(titles(&kept), ["A", "D", "E"]);
+        // hubs
+        let kept = filter_degree(zs, 1..=usize::MAX, 1..=usize::MAX);
+        assert_eq!(titles(&kept), ["B"]);
+    }
+
+    #[test]
+    fn filter_by_ghost_links()
+    {
+        let all = zettelkasten();
+        let kept = filter_ghost_links(all.clone(), &all);
+        assert_eq!(titles(&kept), ["E"]);
+        // the notes that aren't being filtered still exist
+        let kept = filter_ghost_links(all[..1].to_vec(), &all);
+        assert!(kept.is_empty());
+    }
+
+    /// Run the given saved query along with the given `query` arguments, and return the matches
+    /// of `query`
+    fn run_saved(saved: &[&str], given: &[&str]) -> ArgMatches
+    {
+        let args = ["settle", "query"].iter().chain(given);
+        let matches = cli::build().get_matches_from(args);
+        let saved = saved.iter().map(|a| a.to_string()).collect();
+        saved_query_matches(saved, matches.subcommand_matches("query").unwrap())
+            .subcommand_matches("query")
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn saved_query_adds_given_options()
+    {
+        let m = run_saved(&["--title", "A"], &["@q", "--tag", "idea"]);
+        assert_eq!(m.get_one::<String>("TITLE").unwrap(), "A");
+        assert_eq!(m.get_one::<String>("TAG").unwrap(), "idea");
+        assert_eq!(m.get_one::<String>("SAVED_QUERY"), None);
+    }
+
+    #[test]
+    fn given_options_override_saved_ones()
+    {
+        let m = run_saved(
+            &["--title", "A", "--loners"],
+            &["@q", "--title", "B", "--no-loners"],
+        );
+        assert_eq!(m.get_one::<String>("TITLE").unwrap(), "B");
+        assert!(!m.get_flag("LONERS"));
+
+        let m = run_saved(&["--loners"], &["@q"]);
+        assert!(m.get_flag("LONERS"));
+    }
+
+    #[test]
+    fn given_values_may_look_like_the_name_or_an_option()
+    {
+        let m = run_saved(&[], &["@q", "--title", "@q", "--modified-before=-1d"]);
+        assert_eq!(m.get_one::<String>("TITLE").unwrap(), "@q");
+        assert!(m.get_one::<i64>("MODIFIED_BEFORE").is_some());
+    }
+
+    #[test]
+    fn saved_values_may_look_like_an_option()
+    {
+        let cfg = ConfigOptions {
+            queries: serde_yaml::from_str(
+                "q:\n  title: -draft\n  modified-before: -1d\n  path: [A, B]\n  loners: true",
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let saved = cfg.saved_query_args("q").unwrap();
+        let saved: Vec<&str> = saved.iter().map(|a| a.as_str()).collect();
+
+        let m = run_saved(&saved, &["@q"]);
+        assert_eq!(m.get_one::<String>("TITLE").unwrap(), "-draft");
+        assert!(m.get_one::<i64>("MODIFIED_BEFORE").is_some());
+        let path: Vec<&String> = m.get_many::<String>("PATH").unwrap().collect();
+        assert_eq!(path, ["A", "B"]);
+        assert!(m.get_flag("LONERS"));
+    }
+}
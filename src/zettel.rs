@@ -1,9 +1,11 @@
 use chrono::prelude::*;
 use rayon::prelude::*;
 use regex::Regex;
+use std::cmp::Ordering;
 
 use crate::config::ConfigOptions;
 use crate::io::*;
+use crate::search::minhash;
 
 /// Find and return wiki-style links inside of `contents` string
 /// wiki-style links are of the form `[[LINK]]`
@@ -42,7 +44,9 @@ pub fn strip_multiple_whitespace(s: &str) -> String
     re.replace_all(s, " ").to_string()
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+/// Zettel are compared, and sorted, by their title, project, tags, links and backlinks; their
+/// timestamps and fingerprints are left out, as they say nothing about which note it is
+#[derive(Clone)]
 pub struct Zettel
 {
     pub title: String,
@@ -50,6 +54,53 @@ pub struct Zettel
     pub tags: Vec<String>,
     pub links: Vec<String>,
     pub backlinks: Vec<String>,
+    /// UNIX timestamp of the creation of the file
+    pub created: i64,
+    /// UNIX timestamp of the last modification of the file
+    pub modified: i64,
+    /// MinHash signature of the contents of the file, for finding near-duplicates
+    pub fingerprint: Vec<u32>,
+}
+
+impl Zettel
+{
+    /// Return the fields by which Zettel are compared, in order
+    fn identity(&self) -> (&str, &str, &[String], &[String], &[String])
+    {
+        (
+            &self.title,
+            &self.project,
+            &self.tags,
+            &self.links,
+            &self.backlinks,
+        )
+    }
+}
+
+impl PartialEq for Zettel
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.identity() == other.identity()
+    }
+}
+
+impl Eq for Zettel {}
+
+impl PartialOrd for Zettel
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Zettel
+{
+    fn cmp(&self, other: &Self) -> Ordering
+    {
+        self.identity().cmp(&other.identity())
+    }
 }
 
 impl Zettel
@@ -63,6 +114,9 @@ impl Zettel
             tags: vec![],
             links: vec![],
             backlinks: vec![],
+            created: 0,
+            modified: 0,
+            fingerprint: vec![],
         }
     }
 
@@ -91,6 +145,8 @@ impl Zettel
         let mut zettel = Zettel::new(&title, &project);
         zettel.links = find_links(&contents);
         zettel.tags = find_tags(&contents);
+        zettel.fingerprint = minhash(&contents);
+        (zettel.created, zettel.modified) = file_times(&zettel.filename(cfg));
         zettel
     }
 
@@ -144,6 +200,18 @@ impl Zettel
         .to_string()
     }
 
+    /// Return the numbers of all the lines (counting from 1) on which the given pattern matches,
+    /// along with all the lines of the Zettel. A match that spans several lines only counts for
+    /// the line it starts on.
+    pub fn find_pattern_lines(
+        &self,
+        cfg: &ConfigOptions,
+        pattern: &str,
+    ) -> (Vec<usize>, Vec<String>)
+    {
+        pattern_lines(&file_to_string(&self.filename(cfg)), pattern)
+    }
+
     /// Given the contents of a template file, replace all placeholders with their proper value
     fn replace_template_placeholders(&self, contents: &str) -> String
     {
@@ -155,3 +223,73 @@ impl Zettel
             .to_string()
     }
 }
+
+/// Return the numbers of all the lines (counting from 1) of `contents` on which the given pattern
+/// matches, along with all the lines themselves
+fn pattern_lines(contents: &str, pattern: &str) -> (Vec<usize>, Vec<String>)
+{
+    let re = Regex::new(&format!(r"(?i){}", pattern)).unwrap();
+
+    let mut line_numbers: Vec<usize> = re
+        .find_iter(contents)
+        .map(|m| contents[..m.start()].matches('\n').count() + 1)
+        .collect();
+    line_numbers.dedup();
+
+    let mut lines: Vec<String> = contents.lines().map(|l| l.to_string()).collect();
+    // `lines` leaves out the empty line after a final newline, which is only worth keeping if the
+    // pattern matches on it (as `(?m)^$` does)
+    if line_numbers.last() > Some(&lines.len()) {
+        lines.push(String::new());
+    }
+    (line_numbers, lines)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn timestamps_and_fingerprints_dont_tell_notes_apart()
+    {
+        let a = Zettel::new("A", "");
+        let mut a2 = a.clone();
+        a2.created = 1;
+        a2.modified = 2;
+        a2.fingerprint = vec![3];
+        assert!(a == a2);
+
+        // the earlier note still sorts after the other one, by title
+        let mut b = Zettel::new("B", "");
+        b.created = -1;
+        assert!(a2 < b);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn pattern_lines_are_counted_once_from_one()
+    {
+        let contents = "# Title\nsome text, More text\n\nno match\nmore\nand\nover";
+        let (line_numbers, lines) = pattern_lines(contents, "more");
+        assert_eq!(line_numbers, [2, 5]);
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[4], "more");
+
+        // a match spanning lines counts for the line it starts on
+        assert_eq!(pattern_lines(contents, r"more\nand").0, [5]);
+        assert!(pattern_lines(contents, "absent").0.is_empty());
+    }
+
+    #[test]
+    fn the_line_after_a_final_newline_may_match()
+    {
+        let (line_numbers, lines) = pattern_lines("a\r\nb\n", "(?m)^$");
+        assert_eq!(line_numbers, [3]);
+        assert_eq!(lines, ["a", "b", ""]);
+
+        // otherwise, it isn't a line of its own
+        assert_eq!(pattern_lines("a\r\nb\n", "b").1, ["a", "b"]);
+        assert_eq!(pattern_lines("", "^$"), (vec![1], vec![String::new()]));
+    }
+}